@@ -0,0 +1,288 @@
+//!
+//! A synchronous client for applications that don't run an async runtime.
+//!
+//! # Example
+//! ```no_run
+//! use hedgehog_rs::client::PosthogClient;
+//! use hedgehog_rs::data::{Event, Person};
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let client = PosthogClient::builder()
+//!         .base_url("https://app.posthog.com")
+//!         .api_key("your-api-key")
+//!         .build_blocking()?;
+//!
+//!     let mut person = Person::builder().distinct_id("12345").build()?;
+//!
+//!     client.enqueue_event(&person, Event::builder().name("test event").build()?)?;
+//!
+//!     if client.is_feature_enabled(&person, "test_feature_flag")? {
+//!         println!("Feature flag is enabled");
+//!     }
+//!
+//!     let feature_flags = client.feature_flags(&mut person)?;
+//!     println!("{:?}", feature_flags.get_json_flag("json_feature_flag"));
+//!
+//!     client.shutdown(std::time::Duration::from_secs(5))?;
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use std::{collections::HashMap, error::Error, sync::Arc};
+
+use serde_json::Value;
+use tokio::{
+    runtime::{Builder, Runtime},
+    time::Duration,
+};
+
+use crate::{
+    client::{
+        FlushReport, PosthogClient as AsyncPosthogClient, PosthogClientBuilder, QueueFullPolicy,
+    },
+    data::{
+        EarlyAccessFeature, Event, FeatureFlag, FeatureFlagCollection, FeatureFlagData, Group,
+        Person,
+    },
+    error::PosthogError,
+};
+
+/// A [`PosthogClient`](crate::client::PosthogClient) that runs its background work on a runtime it
+/// owns, and exposes every method synchronously.
+///
+/// Built with [`PosthogClientBuilder::build_blocking`]. Clones share the same queue and runtime.
+/// Methods must not be called from within an async runtime, and queued events are only sent
+/// reliably if [`PosthogClient::shutdown`] is called before the last clone is dropped.
+#[derive(Debug, Clone)]
+pub struct PosthogClient {
+    client: AsyncPosthogClient,
+    runtime: Arc<Runtime>,
+}
+
+impl PosthogClient {
+    pub(crate) fn new(builder: PosthogClientBuilder) -> Result<Self, PosthogError> {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("hedgehog-rs")
+            .enable_all()
+            .build()
+            .map_err(PosthogError::RuntimeError)?;
+
+        let client = builder.runtime_handle(runtime.handle().clone()).build()?;
+
+        Ok(Self {
+            client,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// The underlying async client, for APIs that take one such as [`Event::enqueue`].
+    pub fn async_client(&self) -> &AsyncPosthogClient {
+        &self.client
+    }
+
+    /// Enqueues an event, blocking until there is room in the queue if it is full and the client
+    /// was built with [`QueueFullPolicy::WaitAsync`](crate::client::QueueFullPolicy::WaitAsync).
+    pub fn enqueue_event(&self, person: &Person, event: Event) -> Result<(), PosthogError> {
+        // Only waiting needs the runtime, which can't be blocked on from within another one.
+        if self.client.queue.queue_full_policy() == QueueFullPolicy::WaitAsync {
+            self.runtime
+                .block_on(self.client.enqueue_event_async(person, event))
+        } else {
+            self.client.enqueue_event(person, event)
+        }
+    }
+
+    /// See [`AsyncPosthogClient::enqueue_anonymous_event`].
+    pub fn enqueue_anonymous_event(&self, event: Event) -> Result<(), PosthogError> {
+        self.client.enqueue_anonymous_event(event)
+    }
+
+    pub fn capture_event(&self, person: &Person, event: Event) -> Result<(), PosthogError> {
+        self.runtime
+            .block_on(self.client.capture_event(person, event))
+    }
+
+    /// See [`AsyncPosthogClient::capture_exception`].
+    pub fn capture_exception<E>(&self, person: &Person, error: &E) -> Result<(), PosthogError>
+    where
+        E: Error + ?Sized,
+    {
+        self.runtime
+            .block_on(self.client.capture_exception(person, error))
+    }
+
+    pub fn enqueue_exception<E>(&self, person: &Person, error: &E) -> Result<(), PosthogError>
+    where
+        E: Error + ?Sized,
+    {
+        self.client.enqueue_exception(person, error)
+    }
+
+    /// See [`AsyncPosthogClient::install_panic_hook`].
+    pub fn install_panic_hook(&self, person: Person, flush_timeout: Duration) {
+        self.client.install_panic_hook(person, flush_timeout)
+    }
+
+    pub fn enqueue_identify(&self, person: &Person) -> Result<(), PosthogError> {
+        self.client.enqueue_identify(person)
+    }
+
+    /// See [`AsyncPosthogClient::enqueue_set_person_properties`].
+    pub fn enqueue_set_person_properties(
+        &self,
+        person: &Person,
+        set: HashMap<String, Value>,
+        set_once: HashMap<String, Value>,
+        unset: Vec<String>,
+    ) -> Result<(), PosthogError> {
+        self.client
+            .enqueue_set_person_properties(person, set, set_once, unset)
+    }
+
+    pub fn enqueue_alias(
+        &self,
+        person: &Person,
+        alias: impl Into<String>,
+    ) -> Result<(), PosthogError> {
+        self.client.enqueue_alias(person, alias)
+    }
+
+    pub fn enqueue_group_identify(&self, group: &Group) -> Result<(), PosthogError> {
+        self.client.enqueue_group_identify(group)
+    }
+
+    pub fn enqueue_page_view_event(
+        &self,
+        person: &Person,
+        title: impl Into<String>,
+    ) -> Result<(), PosthogError> {
+        self.client.enqueue_page_view_event(person, title)
+    }
+
+    pub fn enqueue_screen_view_event(
+        &self,
+        person: &Person,
+        screen_name: impl Into<String>,
+    ) -> Result<(), PosthogError> {
+        self.client.enqueue_screen_view_event(person, screen_name)
+    }
+
+    /// See [`AsyncPosthogClient::feature_flags`].
+    pub fn feature_flags(
+        &self,
+        person: &mut Person,
+    ) -> Result<FeatureFlagCollection, PosthogError> {
+        self.runtime.block_on(self.client.feature_flags(person))
+    }
+
+    /// See [`AsyncPosthogClient::get_feature_flag`].
+    pub fn get_feature_flag(
+        &self,
+        person: &Person,
+        key: &str,
+    ) -> Result<Option<FeatureFlag>, PosthogError> {
+        self.runtime
+            .block_on(self.client.get_feature_flag(person, key))
+    }
+
+    /// See [`AsyncPosthogClient::is_feature_enabled`].
+    pub fn is_feature_enabled(&self, person: &Person, key: &str) -> Result<bool, PosthogError> {
+        self.runtime
+            .block_on(self.client.is_feature_enabled(person, key))
+    }
+
+    /// See [`AsyncPosthogClient::get_feature_flag_payload`].
+    pub fn get_feature_flag_payload(
+        &self,
+        person: &Person,
+        key: &str,
+    ) -> Result<Option<FeatureFlagData>, PosthogError> {
+        self.runtime
+            .block_on(self.client.get_feature_flag_payload(person, key))
+    }
+
+    pub fn enqueue_feature_flag_called_event(
+        &self,
+        person: &Person,
+        feature_flag: impl Into<String>,
+        feature_flag_variant: impl Into<String>,
+    ) -> Result<(), PosthogError> {
+        self.client
+            .enqueue_feature_flag_called_event(person, feature_flag, feature_flag_variant)
+    }
+
+    pub fn early_access_features(&self) -> Result<Vec<EarlyAccessFeature>, PosthogError> {
+        self.runtime.block_on(self.client.early_access_features())
+    }
+
+    pub fn enqueue_early_access_feature_enrollment(
+        &self,
+        person: &Person,
+        feature: impl Into<String>,
+        is_enrolled: bool,
+    ) -> Result<(), PosthogError> {
+        self.client
+            .enqueue_early_access_feature_enrollment(person, feature, is_enrolled)
+    }
+
+    /// See [`AsyncPosthogClient::register`].
+    pub fn register(&self, key: impl Into<String>, value: impl Into<Value>) {
+        self.client.register(key, value)
+    }
+
+    pub fn unregister(&self, key: &str) {
+        self.client.unregister(key)
+    }
+
+    /// See [`AsyncPosthogClient::import_events`].
+    pub fn import_events<'a>(
+        &self,
+        events: impl IntoIterator<Item = (&'a Person, Event)>,
+    ) -> Result<FlushReport, PosthogError> {
+        self.runtime.block_on(self.client.import_events(events))
+    }
+
+    /// Sends every queued event and waits for all in-flight requests to complete.
+    pub fn flush(&self) -> Result<FlushReport, PosthogError> {
+        self.runtime.block_on(self.client.flush())
+    }
+
+    /// See [`AsyncPosthogClient::shutdown`].
+    pub fn shutdown(&self, timeout: Duration) -> Result<FlushReport, PosthogError> {
+        self.runtime.block_on(self.client.shutdown(timeout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        client::PosthogClient as AsyncPosthogClient,
+        data::{Event, Person},
+        testing::Recorder,
+    };
+
+    #[test]
+    fn enqueue_event_works_within_another_runtime() {
+        let recorder = Recorder::new();
+        let client = AsyncPosthogClient::builder()
+            .base_url("http://localhost")
+            .api_key("test-api-key")
+            .recorder(recorder.clone())
+            .build_blocking()
+            .unwrap();
+        let person = Person::builder().distinct_id("user-1").build().unwrap();
+
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                client
+                    .enqueue_event(&person, Event::builder().name("a").build().unwrap())
+                    .unwrap();
+            });
+
+        recorder.assert_captured("a");
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use serde_json::Value;
+use tokio::sync::Notify;
+
+/// What happens when an event is enqueued while the queue is at capacity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QueueFullPolicy {
+    /// Discard the event being enqueued.
+    #[default]
+    DropNewest,
+
+    /// Discard the oldest queued event to make room for the new one.
+    DropOldest,
+
+    /// Same as [`QueueFullPolicy::Error`], except that
+    /// [`PosthogClient::enqueue_event_async`](crate::client::PosthogClient::enqueue_event_async)
+    /// and the blocking client's `enqueue_event` wait until there is room in the queue.
+    WaitAsync,
+
+    /// Return [`PosthogError::QueueFull`](crate::error::PosthogError::QueueFull).
+    Error,
+}
+
+pub(crate) enum PushError {
+    /// The buffer is at capacity, the event is handed back to the caller.
+    Full(Value),
+
+    /// The queue has been shut down.
+    Closed,
+}
+
+/// Holds the events waiting to be sent in the next batch.
+#[derive(Debug)]
+pub(crate) struct EventBuffer {
+    events: Mutex<VecDeque<Value>>,
+    capacity: usize,
+    batch_size: usize,
+    closed: AtomicBool,
+    space: Notify,
+    batch_ready: Notify,
+}
+
+impl EventBuffer {
+    pub(crate) fn new(capacity: usize, batch_size: usize) -> Self {
+        Self {
+            events: Mutex::new(VecDeque::new()),
+            capacity: capacity.max(1),
+            batch_size: batch_size.max(1),
+            closed: AtomicBool::new(false),
+            space: Notify::new(),
+            batch_ready: Notify::new(),
+        }
+    }
+
+    pub(crate) fn push(&self, event: Value) -> Result<(), PushError> {
+        if self.is_closed() {
+            return Err(PushError::Closed);
+        }
+
+        let mut events = self.events.lock().unwrap();
+        if events.len() >= self.capacity {
+            return Err(PushError::Full(event));
+        }
+
+        events.push_back(event);
+        self.notify_if_batch_ready(events.len());
+
+        Ok(())
+    }
+
+    /// Pushes the event, discarding the oldest queued event if the buffer is at capacity.
+    ///
+    /// Returns whether an event was discarded.
+    pub(crate) fn push_evicting(&self, event: Value) -> Result<bool, PushError> {
+        if self.is_closed() {
+            return Err(PushError::Closed);
+        }
+
+        let mut events = self.events.lock().unwrap();
+        let evicted = events.len() >= self.capacity && events.pop_front().is_some();
+
+        events.push_back(event);
+        self.notify_if_batch_ready(events.len());
+
+        Ok(evicted)
+    }
+
+    /// Pushes the event, waiting for room if the buffer is at capacity.
+    pub(crate) async fn push_wait(&self, mut event: Value) -> Result<(), PushError> {
+        loop {
+            let space = self.space.notified();
+            tokio::pin!(space);
+            space.as_mut().enable();
+
+            match self.push(event) {
+                Err(PushError::Full(rejected)) => event = rejected,
+                result => return result,
+            }
+
+            space.await;
+        }
+    }
+
+    /// Removes every queued event.
+    pub(crate) fn take_all(&self) -> Vec<Value> {
+        let events = std::mem::take(&mut *self.events.lock().unwrap());
+        self.space.notify_waiters();
+
+        events.into()
+    }
+
+    /// The number of queued events.
+    pub(crate) fn len(&self) -> usize {
+        self.events.lock().unwrap().len()
+    }
+
+    /// Completes once enough events are queued to fill a batch.
+    pub(crate) async fn batch_ready(&self) {
+        self.batch_ready.notified().await;
+    }
+
+    fn notify_if_batch_ready(&self, len: usize) {
+        if len >= self.batch_size {
+            self.batch_ready.notify_one();
+        }
+    }
+
+    /// Rejects every future push and wakes up any waiting producers.
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.space.notify_waiters();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+}
@@ -1,10 +1,46 @@
-use crate::error::PosthogError;
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
-use super::PosthogClient;
+use serde_json::Value;
+
+use tokio::{runtime::Handle, time::Duration};
+
+use crate::{
+    data::{FeatureFlag, FeatureFlagCollection},
+    error::PosthogError,
+};
+
+use super::{
+    flag_cache::FeatureFlagCacheConfig, local_evaluation::LocalEvaluationConfig,
+    queue::QueueConfig, spool::Spool, ClientConfig, PosthogClient, QueueFullPolicy,
+    ReqwestTransport, RetryPolicy, Transport,
+};
 
 pub struct PosthogClientBuilder {
     base_url: Option<String>,
     api_key: Option<String>,
+    personal_api_key: Option<String>,
+    feature_flags_polling_interval: Duration,
+    feature_flag_called_cache_size: usize,
+    feature_flag_cache_ttl: Option<Duration>,
+    feature_flag_cache_size: usize,
+    flag_overrides: HashMap<String, FeatureFlag>,
+    super_properties: HashMap<String, Value>,
+    lib_name: String,
+    lib_version: String,
+    process_person_profile: bool,
+    transport: Option<Arc<dyn Transport>>,
+    retry_policy: RetryPolicy,
+    max_queued_events: usize,
+    queue_full_policy: QueueFullPolicy,
+    flush_interval: Duration,
+    max_batch_size: usize,
+    max_batch_bytes: usize,
+    import_batch_size: usize,
+    spool_directory: Option<PathBuf>,
+    spool_max_bytes: u64,
+    runtime_handle: Option<Handle>,
+    #[cfg(any(test, feature = "testing"))]
+    recorder: Option<crate::testing::Recorder>,
 }
 
 impl PosthogClientBuilder {
@@ -12,6 +48,29 @@ impl PosthogClientBuilder {
         Self {
             base_url: None,
             api_key: None,
+            personal_api_key: None,
+            feature_flags_polling_interval: Duration::from_secs(30),
+            feature_flag_called_cache_size: 50_000,
+            feature_flag_cache_ttl: None,
+            feature_flag_cache_size: 10_000,
+            flag_overrides: HashMap::new(),
+            super_properties: HashMap::new(),
+            lib_name: env!("CARGO_PKG_NAME").to_string(),
+            lib_version: env!("CARGO_PKG_VERSION").to_string(),
+            process_person_profile: true,
+            transport: None,
+            retry_policy: RetryPolicy::default(),
+            max_queued_events: 10_000,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_interval: Duration::from_secs(1),
+            max_batch_size: 100,
+            max_batch_bytes: 5 * 1024 * 1024,
+            import_batch_size: 1000,
+            spool_directory: None,
+            spool_max_bytes: 64 * 1024 * 1024,
+            runtime_handle: None,
+            #[cfg(any(test, feature = "testing"))]
+            recorder: None,
         }
     }
 
@@ -25,10 +84,226 @@ impl PosthogClientBuilder {
         self
     }
 
+    /// Enables local evaluation of feature flags. The flag definitions are fetched with this
+    /// personal API key and refreshed in the background.
+    pub fn personal_api_key(mut self, personal_api_key: impl Into<String>) -> Self {
+        self.personal_api_key = Some(personal_api_key.into());
+        self
+    }
+
+    /// Sets how often flag definitions are refreshed when local evaluation is enabled.
+    /// Defaults to 30 seconds.
+    pub fn feature_flags_polling_interval(mut self, polling_interval: Duration) -> Self {
+        self.feature_flags_polling_interval = polling_interval;
+        self
+    }
+
+    /// Sets how many (distinct ID, flag, variant) combinations are remembered to avoid reporting
+    /// the same `$feature_flag_called` event twice. Defaults to 50,000.
+    pub fn feature_flag_called_cache_size(mut self, cache_size: usize) -> Self {
+        self.feature_flag_called_cache_size = cache_size;
+        self
+    }
+
+    /// Caches the flags evaluated for each person, keyed by distinct ID and properties, for this
+    /// long. Cached flags older than this are still served while they are refreshed in the
+    /// background. Disabled by default.
+    pub fn feature_flag_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.feature_flag_cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Sets how many persons the feature flag cache holds before forgetting the oldest ones.
+    /// Defaults to 10,000.
+    pub fn feature_flag_cache_size(mut self, cache_size: usize) -> Self {
+        self.feature_flag_cache_size = cache_size;
+        self
+    }
+
+    /// Forces the variants and payloads of these flags for every person, regardless of what the
+    /// server or local evaluation returns. Overridden flags are looked up without a request.
+    pub fn flag_overrides(mut self, flag_overrides: FeatureFlagCollection) -> Self {
+        self.flag_overrides.extend(flag_overrides.flags);
+        self
+    }
+
+    /// Registers a property that is attached to every captured event.
+    /// See [`PosthogClient::register`].
+    pub fn super_property(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.super_properties.insert(key.into(), value.into());
+        self
+    }
+
+    /// Overrides the `$lib` property sent with every event, and the name in the `User-Agent`
+    /// header. Defaults to `hedgehog-rs`.
+    pub fn lib_name(mut self, lib_name: impl Into<String>) -> Self {
+        self.lib_name = lib_name.into();
+        self
+    }
+
+    /// Overrides the `$lib_version` property sent with every event, and the version in the
+    /// `User-Agent` header. Defaults to the version of this crate.
+    pub fn lib_version(mut self, lib_version: impl Into<String>) -> Self {
+        self.lib_version = lib_version.into();
+        self
+    }
+
+    /// Sets whether events create or update person profiles by default. Individual events can
+    /// override this with [`EventBuilder::process_person_profile`](crate::data::EventBuilder::process_person_profile).
+    /// Defaults to `true`.
+    pub fn process_person_profile(mut self, process_person_profile: bool) -> Self {
+        self.process_person_profile = process_person_profile;
+        self
+    }
+
+    /// Sends requests through a custom [`Transport`] instead of the default [`ReqwestTransport`].
+    pub fn transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Sends requests through a pre-built [`reqwest::Client`], for example one configured with
+    /// proxies, TLS roots or timeouts.
+    pub fn http_client(self, client: reqwest::Client) -> Self {
+        self.transport(ReqwestTransport::with_client(client))
+    }
+
+    /// Sets how failed requests are retried. Use [`RetryPolicy::disabled`] to turn retries off.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets the maximum number of events waiting to be sent. Defaults to 10,000.
+    pub fn max_queued_events(mut self, max_queued_events: usize) -> Self {
+        self.max_queued_events = max_queued_events;
+        self
+    }
+
+    /// Sets what happens when an event is enqueued while the queue is full.
+    /// Defaults to [`QueueFullPolicy::DropNewest`].
+    pub fn queue_full_policy(mut self, queue_full_policy: QueueFullPolicy) -> Self {
+        self.queue_full_policy = queue_full_policy;
+        self
+    }
+
+    /// Sets how often queued events are sent. Defaults to one second.
+    pub fn flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    /// Sets the maximum number of events sent in a single batch request. The queue is flushed
+    /// early once this many events are waiting. Defaults to 100.
+    pub fn max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size;
+        self
+    }
+
+    /// Sets the maximum size in bytes of a serialized batch request. Larger batches are split,
+    /// and events that do not fit on their own are dropped. Defaults to 5 MiB.
+    pub fn max_batch_bytes(mut self, max_batch_bytes: usize) -> Self {
+        self.max_batch_bytes = max_batch_bytes;
+        self
+    }
+
+    /// Sets the maximum number of events sent in a single batch by
+    /// [`PosthogClient::import_events`]. Defaults to 1,000.
+    pub fn import_batch_size(mut self, import_batch_size: usize) -> Self {
+        self.import_batch_size = import_batch_size;
+        self
+    }
+
+    /// Persists batches to segment files in this directory until they are delivered. Batches that
+    /// could not be delivered are kept, and sent again when the next client is built.
+    pub fn spool_directory(mut self, spool_directory: impl Into<PathBuf>) -> Self {
+        self.spool_directory = Some(spool_directory.into());
+        self
+    }
+
+    /// Sets the maximum size in bytes of the spool directory. Once it is reached, new batches are
+    /// sent without being persisted. Defaults to 64 MiB.
+    pub fn spool_max_bytes(mut self, spool_max_bytes: u64) -> Self {
+        self.spool_max_bytes = spool_max_bytes;
+        self
+    }
+
+    /// Runs the background workers on this runtime. Defaults to the runtime the client is built in.
+    pub fn runtime_handle(mut self, runtime_handle: Handle) -> Self {
+        self.runtime_handle = Some(runtime_handle);
+        self
+    }
+
+    /// Routes every request to the recorder instead of sending it.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn recorder(mut self, recorder: crate::testing::Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Builds a [`blocking::PosthogClient`](crate::blocking::PosthogClient), which runs on its own
+    /// background runtime and can be used outside of async code.
+    #[cfg(feature = "blocking")]
+    pub fn build_blocking(self) -> Result<crate::blocking::PosthogClient, PosthogError> {
+        crate::blocking::PosthogClient::new(self)
+    }
+
     pub fn build(self) -> Result<PosthogClient, PosthogError> {
         let base_url = self.base_url.ok_or(PosthogError::BaseUrlRequired)?;
         let api_key = self.api_key.ok_or(PosthogError::ApiKeyRequired)?;
 
-        Ok(PosthogClient::new(base_url, api_key))
+        let runtime = self
+            .runtime_handle
+            .or_else(|| Handle::try_current().ok())
+            .ok_or(PosthogError::NoRuntime)?;
+
+        let spool = self
+            .spool_directory
+            .map(|directory| Spool::open(directory, self.spool_max_bytes))
+            .transpose()?;
+
+        let local_evaluation =
+            self.personal_api_key
+                .map(|personal_api_key| LocalEvaluationConfig {
+                    personal_api_key,
+                    polling_interval: self.feature_flags_polling_interval,
+                });
+
+        Ok(PosthogClient::new(
+            ClientConfig {
+                api_key,
+                local_evaluation,
+                feature_flag_called_cache_size: self.feature_flag_called_cache_size,
+                feature_flag_cache: self
+                    .feature_flag_cache_ttl
+                    .map(|ttl| FeatureFlagCacheConfig {
+                        ttl,
+                        max_entries: self.feature_flag_cache_size,
+                    }),
+                super_properties: self.super_properties,
+                lib_name: self.lib_name.clone(),
+                lib_version: self.lib_version.clone(),
+                import_batch_size: self.import_batch_size,
+                process_person_profile: self.process_person_profile,
+                flag_overrides: self.flag_overrides,
+            },
+            QueueConfig {
+                base_url,
+                user_agent: format!("{}/{}", self.lib_name, self.lib_version),
+                transport: self
+                    .transport
+                    .unwrap_or_else(|| Arc::new(ReqwestTransport::new())),
+                retry_policy: self.retry_policy,
+                max_queued_events: self.max_queued_events,
+                queue_full_policy: self.queue_full_policy,
+                flush_interval: self.flush_interval,
+                max_batch_size: self.max_batch_size,
+                max_batch_bytes: self.max_batch_bytes,
+                spool,
+                runtime,
+                #[cfg(any(test, feature = "testing"))]
+                recorder: self.recorder,
+            },
+        ))
     }
 }
@@ -45,9 +45,12 @@ impl PosthogClient {
                 api_key: self.api_key.clone(),
             },
             response_tx: Some(tx),
-        });
+            ..Default::default()
+        })?;
 
-        let json = rx.await.map_err(|_| PosthogError::QueueError)??;
+        let json = rx
+            .await
+            .map_err(|error| PosthogError::QueueError(error.into()))??;
         let json = serde_json::from_value::<PartialEarlyAccessFeaturesResponse>(json)?;
 
         Ok(json.early_access_features)
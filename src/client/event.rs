@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use chrono::Utc;
 
 use serde_json::{json, Value};
@@ -21,9 +23,36 @@ impl PosthogClient {
         self.queue.offer(QueuedRequest {
             request: PosthogRequest::CaptureEvent { body: event_json },
             ..Default::default()
-        });
+        })
+    }
 
-        Ok(())
+    /// Enqueues an event under a randomly generated distinct ID, without a person profile unless
+    /// the event explicitly enables one.
+    pub fn enqueue_anonymous_event(&self, mut event: Event) -> Result<(), PosthogError> {
+        let person = Person::builder()
+            .distinct_id(Uuid::new_v4().to_string())
+            .build()?;
+
+        event.process_person_profile.get_or_insert(false);
+
+        self.enqueue_event(&person, event)
+    }
+
+    /// Enqueues an event, waiting for room in the queue if it is full and the client was built
+    /// with [`QueueFullPolicy::WaitAsync`](super::QueueFullPolicy::WaitAsync).
+    pub async fn enqueue_event_async(
+        &self,
+        person: &Person,
+        event: Event,
+    ) -> Result<(), PosthogError> {
+        let event_json = self.get_event_json(person, event);
+
+        self.queue
+            .offer_wait(QueuedRequest {
+                request: PosthogRequest::CaptureEvent { body: event_json },
+                ..Default::default()
+            })
+            .await
     }
 
     pub async fn capture_event(&self, person: &Person, event: Event) -> Result<(), PosthogError> {
@@ -34,19 +63,171 @@ impl PosthogClient {
         self.queue.dispatch_request(QueuedRequest {
             request: PosthogRequest::CaptureEvent { body: event_json },
             response_tx: Some(tx),
+            ..Default::default()
         });
 
-        rx.await.map(|_| ()).map_err(|_| PosthogError::QueueError)
+        rx.await
+            .map_err(|error| PosthogError::QueueError(error.into()))??;
+
+        Ok(())
     }
 
-    fn get_event_json(&self, person: &Person, event: Event) -> Value {
+    pub(crate) fn get_event_json(&self, person: &Person, event: Event) -> Value {
+        let mut properties = HashMap::from([
+            ("$lib".to_string(), Value::String(self.lib_name.clone())),
+            (
+                "$lib_version".to_string(),
+                Value::String(self.lib_version.clone()),
+            ),
+        ]);
+        properties.extend(self.super_properties.read().unwrap().clone());
+        properties.extend(event.build_properties(person));
+
+        if !event
+            .process_person_profile
+            .unwrap_or(self.process_person_profile)
+        {
+            properties.insert("$process_person_profile".to_string(), Value::Bool(false));
+        }
+
         json!({
             "api_key": self.api_key,
-            "uuid": Uuid::new_v4().to_string(),
-            "timestamp": Utc::now(),
+            "uuid": event.uuid.unwrap_or_else(Uuid::new_v4).to_string(),
+            "timestamp": event.timestamp.unwrap_or_else(Utc::now),
             "distinct_id": person.distinct_id,
             "event": event.name,
-            "properties": event.build_properties(person),
+            "properties": properties,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use crate::{
+        data::{Event, Person},
+        testing::Recorder,
+    };
+
+    use super::PosthogClient;
+
+    fn person() -> Person {
+        Person::builder().distinct_id("user-1").build().unwrap()
+    }
+
+    #[tokio::test]
+    async fn events_include_the_library() {
+        let recorder = Recorder::new();
+        let client = recorder.client().unwrap();
+
+        client
+            .enqueue_event(&person(), Event::builder().name("a").build().unwrap())
+            .unwrap();
+
+        let event = recorder.assert_captured("a");
+        assert_eq!(event.property("$lib"), Some(&json!(env!("CARGO_PKG_NAME"))));
+        assert_eq!(
+            event.property("$lib_version"),
+            Some(&json!(env!("CARGO_PKG_VERSION")))
+        );
+
+        let recorder = Recorder::new();
+        let client = PosthogClient::builder()
+            .base_url("http://localhost")
+            .api_key("test-api-key")
+            .recorder(recorder.clone())
+            .lib_name("my-wrapper")
+            .lib_version("1.2.3")
+            .build()
+            .unwrap();
+
+        client
+            .enqueue_event(&person(), Event::builder().name("a").build().unwrap())
+            .unwrap();
+
+        let event = recorder.assert_captured("a");
+        assert_eq!(event.property("$lib"), Some(&json!("my-wrapper")));
+        assert_eq!(event.property("$lib_version"), Some(&json!("1.2.3")));
+    }
+
+    #[tokio::test]
+    async fn events_use_the_given_timestamp_and_uuid() {
+        let recorder = Recorder::new();
+        let client = recorder.client().unwrap();
+        let timestamp = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let uuid = Uuid::new_v4();
+
+        let event = Event::builder()
+            .name("a")
+            .timestamp(timestamp)
+            .uuid(uuid)
+            .build()
+            .unwrap();
+        client.enqueue_event(&person(), event).unwrap();
+
+        let event = recorder.assert_captured("a");
+        assert_eq!(event.json()["timestamp"], json!("2024-01-02T03:04:05Z"));
+        assert_eq!(event.json()["uuid"], json!(uuid.to_string()));
+    }
+
+    #[tokio::test]
+    async fn events_can_skip_person_processing() {
+        let recorder = Recorder::new();
+        let client = recorder.client().unwrap();
+
+        client
+            .enqueue_event(&person(), Event::builder().name("a").build().unwrap())
+            .unwrap();
+        client
+            .enqueue_anonymous_event(Event::builder().name("b").build().unwrap())
+            .unwrap();
+
+        assert_eq!(
+            recorder
+                .assert_captured("a")
+                .property("$process_person_profile"),
+            None
+        );
+        assert_eq!(
+            recorder
+                .assert_captured("b")
+                .property("$process_person_profile"),
+            Some(&json!(false))
+        );
+
+        let recorder = Recorder::new();
+        let client = PosthogClient::builder()
+            .base_url("http://localhost")
+            .api_key("test-api-key")
+            .recorder(recorder.clone())
+            .process_person_profile(false)
+            .build()
+            .unwrap();
+
+        client
+            .enqueue_event(&person(), Event::builder().name("a").build().unwrap())
+            .unwrap();
+        let event = Event::builder()
+            .name("b")
+            .process_person_profile(true)
+            .build()
+            .unwrap();
+        client.enqueue_event(&person(), event).unwrap();
+
+        assert_eq!(
+            recorder
+                .assert_captured("a")
+                .property("$process_person_profile"),
+            Some(&json!(false))
+        );
+        assert_eq!(
+            recorder
+                .assert_captured("b")
+                .property("$process_person_profile"),
+            None
+        );
+    }
+}
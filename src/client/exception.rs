@@ -0,0 +1,234 @@
+use std::{
+    any::type_name,
+    backtrace::{Backtrace, BacktraceStatus},
+    error::Error,
+    panic::PanicHookInfo,
+    sync::mpsc,
+};
+
+use serde_json::{json, Value};
+use tokio::time::Duration;
+
+use crate::{
+    data::{Event, Person},
+    error::PosthogError,
+};
+
+use super::PosthogClient;
+
+impl PosthogClient {
+    /// Sends an error to Posthog error tracking immediately, along with its chain of sources and
+    /// a backtrace of the call site when backtraces are enabled.
+    pub async fn capture_exception<E>(&self, person: &Person, error: &E) -> Result<(), PosthogError>
+    where
+        E: Error + ?Sized,
+    {
+        exception_event(error)?.capture(person, self).await
+    }
+
+    /// Enqueues an error for Posthog error tracking. See [`PosthogClient::capture_exception`].
+    pub fn enqueue_exception<E>(&self, person: &Person, error: &E) -> Result<(), PosthogError>
+    where
+        E: Error + ?Sized,
+    {
+        exception_event(error)?.enqueue(person, self)
+    }
+
+    /// Installs a panic hook that reports panics to Posthog error tracking under the given person,
+    /// then waits up to `flush_timeout` for the queue to be flushed before running the previous hook.
+    ///
+    /// The flush can only make progress if the client's runtime has other threads to run on, so on
+    /// a current-thread runtime panics that happen on the runtime thread are only enqueued.
+    pub fn install_panic_hook(&self, person: Person, flush_timeout: Duration) {
+        let client = self.clone();
+        let runtime = self.queue.runtime().clone();
+        let previous_hook = std::panic::take_hook();
+
+        std::panic::set_hook(Box::new(move |info| {
+            if let Ok(event) = panic_event(info) {
+                client.enqueue_event(&person, event).ok();
+            }
+
+            let client = client.clone();
+            let runtime = runtime.clone();
+            let (done_tx, done_rx) = mpsc::channel();
+
+            std::thread::spawn(move || {
+                runtime.block_on(client.flush()).ok();
+                done_tx.send(()).ok();
+            });
+
+            done_rx.recv_timeout(flush_timeout).ok();
+
+            previous_hook(info);
+        }));
+    }
+}
+
+fn exception_event<E>(error: &E) -> Result<Event, PosthogError>
+where
+    E: Error + ?Sized,
+{
+    let mut exceptions = vec![json!({
+        "type": error_type_name::<E>(error),
+        "value": error.to_string(),
+        "mechanism": {
+            "type": "generic",
+            "handled": true,
+            "synthetic": false,
+        },
+        "stacktrace": stacktrace(&Backtrace::capture()),
+    })];
+
+    let mut source = error.source();
+    while let Some(error) = source {
+        exceptions.push(json!({
+            "type": debug_type_name(error),
+            "value": error.to_string(),
+            "mechanism": {
+                "type": "chained",
+                "handled": true,
+                "synthetic": false,
+            },
+        }));
+
+        source = error.source();
+    }
+
+    Event::builder()
+        .name("$exception")
+        .property("$exception_level", "error")
+        .property("$exception_list", exceptions)
+        .build()
+}
+
+fn panic_event(info: &PanicHookInfo) -> Result<Event, PosthogError> {
+    let payload = info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Box<dyn Any>".to_string());
+
+    let value = match info.location() {
+        Some(location) => format!("{} at {}", message, location),
+        None => message,
+    };
+
+    Event::builder()
+        .name("$exception")
+        .property("$exception_level", "fatal")
+        .property(
+            "$exception_list",
+            json!([{
+                "type": "panic",
+                "value": value,
+                "mechanism": {
+                    "type": "panic",
+                    "handled": false,
+                    "synthetic": false,
+                },
+                "stacktrace": stacktrace(&Backtrace::capture()),
+            }]),
+        )
+        .build()
+}
+
+/// The name of the error type, without its module path.
+fn error_type_name<E>(error: &E) -> String
+where
+    E: Error + ?Sized,
+{
+    let name = type_name::<E>();
+
+    // Trait objects don't carry the name of the concrete type.
+    if name.starts_with("dyn ") {
+        return debug_type_name(error);
+    }
+
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name).to_string()
+}
+
+/// Guesses the type name from the `Debug` output, which starts with it for derived implementations.
+fn debug_type_name(error: &(impl Error + ?Sized)) -> String {
+    let debug = format!("{:?}", error);
+    let name = debug
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .next()
+        .unwrap_or_default();
+
+    if name.is_empty() {
+        "Error".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Parses a captured backtrace into Posthog stack frames, or returns `null` if none was captured.
+fn stacktrace(backtrace: &Backtrace) -> Value {
+    if backtrace.status() != BacktraceStatus::Captured {
+        return Value::Null;
+    }
+
+    let mut frames: Vec<Value> = vec![];
+
+    // Each frame is printed as "N: function", optionally followed by "at file:line:column".
+    for line in backtrace.to_string().lines() {
+        let line = line.trim();
+
+        if let Some(location) = line.strip_prefix("at ") {
+            let Some(frame) = frames.last_mut() else {
+                continue;
+            };
+
+            let mut parts = location.rsplitn(3, ':');
+            let column = parts.next().and_then(|part| part.parse::<u32>().ok());
+            let line_number = parts.next().and_then(|part| part.parse::<u32>().ok());
+            let filename = parts.next().unwrap_or(location);
+
+            frame["filename"] = json!(filename);
+            frame["lineno"] = json!(line_number);
+            frame["colno"] = json!(column);
+            frame["in_app"] = json!(!is_library_path(filename));
+        } else if let Some((index, function)) = line.split_once(": ") {
+            if index.parse::<usize>().is_ok() {
+                frames.push(json!({
+                    "function": function,
+                    "platform": "custom",
+                    "lang": "rust",
+                    "resolved": true,
+                    "in_app": false,
+                }));
+            }
+        }
+    }
+
+    // Drop the frames of the backtrace and panic machinery and of this module, which are always
+    // at the top of the stack.
+    let skipped = frames
+        .iter()
+        .take_while(|frame| {
+            let function = frame["function"].as_str().unwrap_or_default();
+            function.starts_with("std::")
+                || function.starts_with("core::")
+                || function.starts_with("hedgehog_rs::client::exception::")
+                || function.starts_with("<alloc::boxed::Box<F,A> as core::ops::function::Fn")
+        })
+        .count();
+    frames.drain(..skipped);
+
+    // Posthog expects the innermost frame last.
+    frames.reverse();
+
+    json!({
+        "type": "raw",
+        "frames": frames,
+    })
+}
+
+fn is_library_path(filename: &str) -> bool {
+    filename.starts_with("/rustc/")
+        || filename.contains("/.cargo/registry/")
+        || filename.contains("/.cargo/git/")
+}
@@ -5,24 +5,215 @@ use serde_json::{json, Value};
 use tokio::sync::oneshot::channel;
 
 use crate::{
-    data::{Event, FeatureFlag, FeatureFlagCollection, Person, PropertyFilter},
+    data::{
+        parse_payload, Event, FeatureFlag, FeatureFlagCollection, FeatureFlagData, Person,
+        PropertyFilter,
+    },
     error::PosthogError,
 };
 
 use super::{
+    flag_cache::{FeatureFlagCacheKey, FeatureFlagCacheLookup},
     queue::{PosthogRequest::EvaluateFeatureFlags, QueuedRequest},
     PosthogClient,
 };
 
 impl PosthogClient {
+    /// Evaluates every feature flag for the person and stores the result in it.
+    ///
+    /// When local evaluation is enabled, flags are computed in-process and only the ones that can't
+    /// be computed locally are requested from the server, or left out if that request fails. Flag
+    /// overrides set on the client replace the evaluated flags.
     pub async fn feature_flags(
         &self,
         person: &mut Person,
     ) -> Result<FeatureFlagCollection, PosthogError> {
+        let collection = FeatureFlagCollection::new(self.evaluate_feature_flags(person).await?);
+        person.stored_feature_flags = Some(collection.clone());
+
+        Ok(collection)
+    }
+
+    /// Returns a single feature flag for the person, or `None` if it does not exist.
+    ///
+    /// Flags stored in the person are used when present, otherwise they are evaluated. A
+    /// `$feature_flag_called` event is enqueued the first time each variant is seen for the person.
+    pub async fn get_feature_flag(
+        &self,
+        person: &Person,
+        key: &str,
+    ) -> Result<Option<FeatureFlag>, PosthogError> {
+        let feature_flag = self.lookup_feature_flag(person, key).await?;
+
+        if let Some(feature_flag) = &feature_flag {
+            self.report_feature_flag_called(person, key, feature_flag)?;
+        }
+
+        Ok(feature_flag)
+    }
+
+    /// Returns whether a feature flag is enabled for the person, reporting the call like
+    /// [`PosthogClient::get_feature_flag`]. Missing flags are disabled.
+    pub async fn is_feature_enabled(
+        &self,
+        person: &Person,
+        key: &str,
+    ) -> Result<bool, PosthogError> {
+        Ok(self
+            .get_feature_flag(person, key)
+            .await?
+            .is_some_and(|feature_flag| feature_flag.is_enabled()))
+    }
+
+    /// Returns the payload of a feature flag for the person, without reporting the call.
+    pub async fn get_feature_flag_payload(
+        &self,
+        person: &Person,
+        key: &str,
+    ) -> Result<Option<FeatureFlagData>, PosthogError> {
+        Ok(self
+            .lookup_feature_flag(person, key)
+            .await?
+            .and_then(|feature_flag| feature_flag.payload))
+    }
+
+    async fn lookup_feature_flag(
+        &self,
+        person: &Person,
+        key: &str,
+    ) -> Result<Option<FeatureFlag>, PosthogError> {
+        if let Some(feature_flag) = self.flag_overrides.get(key) {
+            return Ok(Some(feature_flag.clone()));
+        }
+
+        if let Some(stored_feature_flags) = &person.stored_feature_flags {
+            return Ok(stored_feature_flags.get(key).cloned());
+        }
+
+        Ok(self.evaluate_feature_flags(person).await?.remove(key))
+    }
+
+    fn report_feature_flag_called(
+        &self,
+        person: &Person,
+        key: &str,
+        feature_flag: &FeatureFlag,
+    ) -> Result<(), PosthogError> {
+        let variant = feature_flag.variant_as_str();
+
+        let is_new = self.feature_flag_called_cache.lock().unwrap().insert(
+            &person.distinct_id,
+            key,
+            &variant,
+        );
+
+        if is_new {
+            self.enqueue_feature_flag_called_event(person, key, variant)?;
+        }
+
+        Ok(())
+    }
+
+    /// Evaluates every flag for the person, using the feature flag cache when it is enabled.
+    async fn evaluate_feature_flags(
+        &self,
+        person: &Person,
+    ) -> Result<HashMap<String, FeatureFlag>, PosthogError> {
+        let Some(cache) = &self.feature_flag_cache else {
+            return self.compute_feature_flags(person).await;
+        };
+
+        let key = FeatureFlagCacheKey::new(person);
+        let lookup = cache.lock().unwrap().get(&key);
+
+        match lookup {
+            FeatureFlagCacheLookup::Fresh(feature_flags) => Ok(feature_flags),
+
+            FeatureFlagCacheLookup::Stale(feature_flags) => {
+                let client = self.clone();
+                let person = person.clone();
+
+                self.queue.runtime().spawn(async move {
+                    let refreshed = client.compute_feature_flags(&person).await;
+
+                    if let Some(cache) = &client.feature_flag_cache {
+                        let mut cache = cache.lock().unwrap();
+
+                        match refreshed {
+                            Ok(feature_flags) => cache.insert(key, feature_flags),
+                            Err(_) => cache.refresh_failed(&key),
+                        }
+                    }
+                });
+
+                Ok(feature_flags)
+            }
+
+            FeatureFlagCacheLookup::Missing => {
+                let feature_flags = self.compute_feature_flags(person).await?;
+
+                cache.lock().unwrap().insert(key, feature_flags.clone());
+
+                Ok(feature_flags)
+            }
+        }
+    }
+
+    async fn compute_feature_flags(
+        &self,
+        person: &Person,
+    ) -> Result<HashMap<String, FeatureFlag>, PosthogError> {
+        let local = self
+            .local_evaluator
+            .as_ref()
+            .and_then(|evaluator| evaluator.evaluate(person));
+
+        let mut feature_flags = match local {
+            Some(local) if !local.is_inconclusive => local.flags,
+
+            // The flags evaluated locally are still returned if the server can't be reached.
+            Some(mut local) => match self.remote_feature_flags(person).await {
+                Ok(remote) => {
+                    for (key, flag) in remote {
+                        local.flags.entry(key).or_insert(flag);
+                    }
+
+                    local.flags
+                }
+
+                Err(_error) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(
+                        error = %_error,
+                        "failed to evaluate the remaining feature flags remotely"
+                    );
+
+                    local.flags
+                }
+            },
+
+            None => self.remote_feature_flags(person).await?,
+        };
+
+        feature_flags.extend(
+            self.flag_overrides
+                .iter()
+                .map(|(key, flag)| (key.clone(), flag.clone())),
+        );
+
+        Ok(feature_flags)
+    }
+
+    async fn remote_feature_flags(
+        &self,
+        person: &Person,
+    ) -> Result<HashMap<String, FeatureFlag>, PosthogError> {
         let json = json!({
             "api_key": self.api_key,
             "distinct_id": person.distinct_id,
             "person_properties": person.build_properties(PropertyFilter::new().include_person_properties(true).include_ip(true)),
+            "groups": person.group_keys(),
+            "group_properties": person.group_properties(),
         });
 
         let (tx, rx) = channel();
@@ -30,37 +221,37 @@ impl PosthogClient {
         self.queue.offer(QueuedRequest {
             request: EvaluateFeatureFlags { body: json },
             response_tx: Some(tx),
-        });
+            ..Default::default()
+        })?;
 
-        let json = rx.await.map_err(|_| PosthogError::QueueError)??;
-        let json = serde_json::from_value::<PartialFeatureFlagResponse>(json)?;
+        let json = rx
+            .await
+            .map_err(|error| PosthogError::QueueError(error.into()))??;
+        let mut json = serde_json::from_value::<PartialFeatureFlagResponse>(json)?;
 
         if json.error_computing_flags {
-            return Err(PosthogError::FeatureFlagError);
+            return Err(PosthogError::FeatureFlagError(
+                "the server failed to compute feature flags".into(),
+            ));
         }
 
         let feature_flags = json
             .feature_flags
             .into_iter()
             .map(|(key, value)| {
-                let payload = json.feature_flag_payloads.get(&key);
+                let payload = json.feature_flag_payloads.remove(&key);
 
                 Ok((
                     key,
                     FeatureFlag {
                         variant: value.into(),
-                        payload: payload
-                            .and_then(|p| serde_json::from_str::<Value>(p).ok())
-                            .map(Into::into),
+                        payload: payload.map(parse_payload),
                     },
                 ))
             })
             .collect::<Result<HashMap<_, _>, PosthogError>>()?;
 
-        let collection = FeatureFlagCollection::new(feature_flags);
-        person.stored_feature_flags = Some(collection.clone());
-
-        Ok(collection)
+        Ok(feature_flags)
     }
 
     pub fn enqueue_feature_flag_called_event(
@@ -86,6 +277,129 @@ struct PartialFeatureFlagResponse {
     error_computing_flags: bool,
     #[serde(rename = "featureFlags")]
     feature_flags: HashMap<String, Value>,
-    #[serde(rename = "featureFlagPayloads")]
-    feature_flag_payloads: HashMap<String, String>,
+    #[serde(rename = "featureFlagPayloads", default)]
+    feature_flag_payloads: HashMap<String, Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::{
+        client::PosthogRequest,
+        data::{FeatureFlagCollection, Person},
+        testing::Recorder,
+    };
+
+    use super::PosthogClient;
+
+    fn person(distinct_id: &str) -> Person {
+        Person::builder().distinct_id(distinct_id).build().unwrap()
+    }
+
+    fn flag_requests(recorder: &Recorder) -> usize {
+        recorder
+            .requests()
+            .iter()
+            .filter(|request| matches!(request, PosthogRequest::EvaluateFeatureFlags { .. }))
+            .count()
+    }
+
+    #[tokio::test]
+    async fn feature_flag_called_is_reported_once_per_variant() {
+        let recorder = Recorder::new();
+        recorder.set_feature_flags([("onboarding", json!("control"))]);
+        let client = recorder.client().unwrap();
+
+        for _ in 0..3 {
+            client
+                .get_feature_flag(&person("user-1"), "onboarding")
+                .await
+                .unwrap();
+        }
+        client
+            .get_feature_flag(&person("user-2"), "onboarding")
+            .await
+            .unwrap();
+        client
+            .get_feature_flag_payload(&person("user-3"), "onboarding")
+            .await
+            .unwrap();
+
+        recorder.assert_captured_times("$feature_flag_called", 2);
+
+        let event = recorder.events_for("user-1").pop().unwrap();
+        assert_eq!(event.property("$feature_flag"), Some(&json!("onboarding")));
+        assert_eq!(
+            event.property("$feature_flag_response"),
+            Some(&json!("control"))
+        );
+
+        recorder.set_feature_flags([("onboarding", json!("test"))]);
+        client
+            .get_feature_flag(&person("user-1"), "onboarding")
+            .await
+            .unwrap();
+
+        recorder.assert_captured_times("$feature_flag_called", 3);
+    }
+
+    #[tokio::test]
+    async fn overrides_replace_evaluated_flags() {
+        let recorder = Recorder::new();
+        recorder.set_feature_flags([("onboarding", json!(false)), ("checkout", json!(true))]);
+        let client = PosthogClient::builder()
+            .base_url("http://localhost")
+            .api_key("test-api-key")
+            .recorder(recorder.clone())
+            .flag_overrides(FeatureFlagCollection::from_map([("onboarding", true)]))
+            .build()
+            .unwrap();
+
+        assert!(client
+            .is_feature_enabled(&person("user-1"), "onboarding")
+            .await
+            .unwrap());
+        assert_eq!(flag_requests(&recorder), 0);
+
+        let flags = client.feature_flags(&mut person("user-1")).await.unwrap();
+        assert!(flags["onboarding"].is_enabled());
+        assert!(flags["checkout"].is_enabled());
+        assert_eq!(flag_requests(&recorder), 1);
+    }
+
+    #[tokio::test]
+    async fn cached_flags_are_refreshed_after_the_ttl() {
+        let recorder = Recorder::new();
+        recorder.set_feature_flags([("onboarding", json!(true))]);
+        let client = PosthogClient::builder()
+            .base_url("http://localhost")
+            .api_key("test-api-key")
+            .recorder(recorder.clone())
+            .feature_flag_cache_ttl(Duration::from_millis(50))
+            .build()
+            .unwrap();
+
+        client.feature_flags(&mut person("user-1")).await.unwrap();
+        client.feature_flags(&mut person("user-1")).await.unwrap();
+        assert_eq!(flag_requests(&recorder), 1);
+
+        client.feature_flags(&mut person("user-2")).await.unwrap();
+        assert_eq!(flag_requests(&recorder), 2);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        recorder.set_feature_flags([("onboarding", json!(false))]);
+
+        // The stale flags are served while they are refreshed in the background.
+        let flags = client.feature_flags(&mut person("user-1")).await.unwrap();
+        assert!(flags["onboarding"].is_enabled());
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(flag_requests(&recorder), 3);
+
+        let flags = client.feature_flags(&mut person("user-1")).await.unwrap();
+        assert!(!flags["onboarding"].is_enabled());
+    }
 }
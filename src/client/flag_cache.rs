@@ -0,0 +1,130 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    time::Instant,
+};
+
+use serde_json::json;
+use tokio::time::Duration;
+
+use crate::data::{FeatureFlag, Person, PropertyFilter};
+
+/// Settings used to enable the feature flag cache.
+#[derive(Debug)]
+pub(crate) struct FeatureFlagCacheConfig {
+    pub(crate) ttl: Duration,
+    pub(crate) max_entries: usize,
+}
+
+/// Identifies a person together with the properties their flags were evaluated with.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct FeatureFlagCacheKey {
+    distinct_id: String,
+    properties_hash: u64,
+}
+
+impl FeatureFlagCacheKey {
+    pub(crate) fn new(person: &Person) -> Self {
+        // Objects are serialized with sorted keys, so equal properties produce the same string.
+        let properties = json!({
+            "person_properties": person.build_properties(
+                PropertyFilter::new()
+                    .include_person_properties(true)
+                    .include_ip(true)
+            ),
+            "groups": person.group_keys(),
+            "group_properties": person.group_properties(),
+        });
+
+        let mut hasher = DefaultHasher::new();
+        properties.to_string().hash(&mut hasher);
+
+        Self {
+            distinct_id: person.distinct_id.clone(),
+            properties_hash: hasher.finish(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FeatureFlagCacheEntry {
+    flags: HashMap<String, FeatureFlag>,
+    fetched_at: Instant,
+    is_refreshing: bool,
+}
+
+pub(crate) enum FeatureFlagCacheLookup {
+    Fresh(HashMap<String, FeatureFlag>),
+    /// The flags are older than the TTL. The caller is responsible for refreshing them.
+    Stale(HashMap<String, FeatureFlag>),
+    Missing,
+}
+
+/// Remembers the flags evaluated for each person, so repeated lookups are served without a request.
+///
+/// Entries older than the TTL are still served while they are refreshed in the background. The
+/// oldest entries are forgotten once the cache is full.
+#[derive(Debug)]
+pub(crate) struct FeatureFlagCache {
+    entries: HashMap<FeatureFlagCacheKey, FeatureFlagCacheEntry>,
+    order: VecDeque<FeatureFlagCacheKey>,
+    ttl: Duration,
+    max_entries: usize,
+}
+
+impl FeatureFlagCache {
+    pub(crate) fn new(config: FeatureFlagCacheConfig) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            ttl: config.ttl,
+            max_entries: config.max_entries,
+        }
+    }
+
+    /// Looks up the flags of a person. Stale entries are only reported as such once, until they
+    /// are refreshed or the refresh fails, so a single refresh is started for each entry.
+    pub(crate) fn get(&mut self, key: &FeatureFlagCacheKey) -> FeatureFlagCacheLookup {
+        let Some(entry) = self.entries.get_mut(key) else {
+            return FeatureFlagCacheLookup::Missing;
+        };
+
+        if entry.fetched_at.elapsed() < self.ttl || entry.is_refreshing {
+            return FeatureFlagCacheLookup::Fresh(entry.flags.clone());
+        }
+
+        entry.is_refreshing = true;
+        FeatureFlagCacheLookup::Stale(entry.flags.clone())
+    }
+
+    pub(crate) fn insert(&mut self, key: FeatureFlagCacheKey, flags: HashMap<String, FeatureFlag>) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let entry = FeatureFlagCacheEntry {
+            flags,
+            fetched_at: Instant::now(),
+            is_refreshing: false,
+        };
+
+        if self.entries.insert(key.clone(), entry).is_some() {
+            return;
+        }
+
+        if self.order.len() >= self.max_entries {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.order.push_back(key);
+    }
+
+    /// Keeps serving the previous flags, and allows another refresh to be started.
+    pub(crate) fn refresh_failed(&mut self, key: &FeatureFlagCacheKey) {
+        if let Some(entry) = self.entries.get_mut(key) {
+            entry.is_refreshing = false;
+        }
+    }
+}
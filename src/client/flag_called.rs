@@ -0,0 +1,50 @@
+use std::collections::{HashSet, VecDeque};
+
+/// Remembers which `$feature_flag_called` events were already sent, so each
+/// (distinct ID, flag, variant) combination is only reported once.
+///
+/// The oldest entries are forgotten once the cache is full.
+#[derive(Debug)]
+pub(crate) struct FeatureFlagCalledCache {
+    seen: HashSet<(String, String, String)>,
+    order: VecDeque<(String, String, String)>,
+    capacity: usize,
+}
+
+impl FeatureFlagCalledCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Records the combination, returning whether it had not been seen before.
+    pub(crate) fn insert(&mut self, distinct_id: &str, feature_flag: &str, variant: &str) -> bool {
+        if self.capacity == 0 {
+            return true;
+        }
+
+        let key = (
+            distinct_id.to_string(),
+            feature_flag.to_string(),
+            variant.to_string(),
+        );
+
+        if self.seen.contains(&key) {
+            return false;
+        }
+
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+
+        self.seen.insert(key.clone());
+        self.order.push_back(key);
+
+        true
+    }
+}
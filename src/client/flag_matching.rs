@@ -0,0 +1,569 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use sha1::{Digest, Sha1};
+
+use crate::data::{parse_payload, FeatureFlag, FeatureFlagData, Person, PropertyFilter};
+
+/// Scale used to map the first 15 hex digits of a SHA1 hash to `[0, 1]`.
+const LONG_SCALE: f64 = 0xFFFFFFFFFFFFFFFu64 as f64;
+
+/// The response of the `/api/feature_flag/local_evaluation` endpoint.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct FlagDefinitions {
+    #[serde(default)]
+    pub(crate) flags: Vec<FlagDefinition>,
+    /// Maps group type indexes to group type names.
+    #[serde(default)]
+    pub(crate) group_type_mapping: HashMap<String, String>,
+}
+
+impl FlagDefinitions {
+    /// Evaluates every flag for the person, returning the keys of the flags that can't be
+    /// evaluated locally separately.
+    pub(crate) fn evaluate(&self, person: &Person) -> (HashMap<String, FeatureFlag>, Vec<String>) {
+        let mut flags = HashMap::new();
+        let mut inconclusive = vec![];
+
+        for definition in &self.flags {
+            match definition.evaluate(person, &self.group_type_mapping) {
+                Ok(flag) => {
+                    flags.insert(definition.key.clone(), flag);
+                }
+                Err(InconclusiveMatch) => inconclusive.push(definition.key.clone()),
+            }
+        }
+
+        (flags, inconclusive)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct FlagDefinition {
+    pub(crate) key: String,
+    #[serde(default)]
+    active: bool,
+    #[serde(default)]
+    filters: Option<FlagFilters>,
+    #[serde(default)]
+    ensure_experience_continuity: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct FlagFilters {
+    #[serde(default)]
+    groups: Option<Vec<FlagCondition>>,
+    #[serde(default)]
+    multivariate: Option<FlagMultivariate>,
+    #[serde(default)]
+    payloads: Option<HashMap<String, Value>>,
+    #[serde(default)]
+    aggregation_group_type_index: Option<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct FlagCondition {
+    #[serde(default)]
+    properties: Option<Vec<FlagProperty>>,
+    #[serde(default)]
+    rollout_percentage: Option<f64>,
+    #[serde(default)]
+    variant: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct FlagMultivariate {
+    #[serde(default)]
+    variants: Vec<FlagVariant>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct FlagVariant {
+    key: String,
+    rollout_percentage: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct FlagProperty {
+    key: String,
+    #[serde(default)]
+    operator: Option<String>,
+    #[serde(default)]
+    value: Value,
+    #[serde(rename = "type", default)]
+    kind: Option<String>,
+    /// The compiled `regex` or `not_regex` pattern, or `None` if it is invalid.
+    #[serde(skip)]
+    regex: OnceLock<Option<Regex>>,
+}
+
+/// The flag can't be evaluated locally, and has to be computed by the server.
+#[derive(Debug)]
+pub(crate) struct InconclusiveMatch;
+
+impl FlagDefinition {
+    /// Evaluates the flag for a person, the same way the Posthog server does.
+    pub(crate) fn evaluate(
+        &self,
+        person: &Person,
+        group_type_mapping: &HashMap<String, String>,
+    ) -> Result<FeatureFlag, InconclusiveMatch> {
+        let default_filters = FlagFilters::default();
+        let filters = self.filters.as_ref().unwrap_or(&default_filters);
+
+        // Flags that must stay consistent across identities need server-side state.
+        if self.ensure_experience_continuity {
+            return Err(InconclusiveMatch);
+        }
+
+        // Group flags are bucketed by group key and matched against the group's properties.
+        let (id, properties) = match filters.aggregation_group_type_index {
+            Some(index) => {
+                let group_type = group_type_mapping
+                    .get(&index.to_string())
+                    .ok_or(InconclusiveMatch)?;
+
+                match person.groups.get(group_type) {
+                    Some(group) => (
+                        group.group_key.clone(),
+                        group.properties.clone().unwrap_or_default(),
+                    ),
+                    None => {
+                        return Ok(FeatureFlag {
+                            variant: FeatureFlagData::Boolean(false),
+                            payload: None,
+                        })
+                    }
+                }
+            }
+
+            None => (
+                person.distinct_id.clone(),
+                person.build_properties(
+                    PropertyFilter::new()
+                        .include_person_properties(true)
+                        .include_ip(true),
+                ),
+            ),
+        };
+
+        let variant = if self.active {
+            self.match_conditions(filters, &id, &properties)?
+        } else {
+            FeatureFlagData::Boolean(false)
+        };
+
+        let payload_key = match &variant {
+            FeatureFlagData::String(key) => Some(key.clone()),
+            FeatureFlagData::Boolean(true) => Some("true".to_string()),
+            _ => None,
+        };
+
+        let payload = payload_key
+            .and_then(|key| filters.payloads.as_ref()?.get(&key).cloned())
+            .map(parse_payload);
+
+        Ok(FeatureFlag { variant, payload })
+    }
+
+    fn match_conditions(
+        &self,
+        filters: &FlagFilters,
+        distinct_id: &str,
+        properties: &HashMap<String, Value>,
+    ) -> Result<FeatureFlagData, InconclusiveMatch> {
+        let variants = filters
+            .multivariate
+            .as_ref()
+            .map(|multivariate| multivariate.variants.as_slice())
+            .unwrap_or_default();
+
+        // Conditions with a variant override are evaluated first.
+        let mut conditions = filters.groups.iter().flatten().collect::<Vec<_>>();
+        conditions.sort_by_key(|condition| condition.variant.is_none());
+
+        let mut is_inconclusive = false;
+
+        for condition in conditions {
+            match self.is_condition_match(condition, distinct_id, properties) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(InconclusiveMatch) => {
+                    is_inconclusive = true;
+                    continue;
+                }
+            }
+
+            let variant = condition
+                .variant
+                .as_ref()
+                .filter(|key| variants.iter().any(|variant| &variant.key == *key))
+                .cloned()
+                .or_else(|| self.matching_variant(variants, distinct_id));
+
+            return Ok(match variant {
+                Some(variant) => FeatureFlagData::String(variant),
+                None => FeatureFlagData::Boolean(true),
+            });
+        }
+
+        if is_inconclusive {
+            return Err(InconclusiveMatch);
+        }
+
+        Ok(FeatureFlagData::Boolean(false))
+    }
+
+    fn is_condition_match(
+        &self,
+        condition: &FlagCondition,
+        distinct_id: &str,
+        properties: &HashMap<String, Value>,
+    ) -> Result<bool, InconclusiveMatch> {
+        let filters = condition.properties.as_deref().unwrap_or_default();
+
+        for filter in filters {
+            if !filter.matches(properties)? {
+                return Ok(false);
+            }
+        }
+
+        match condition.rollout_percentage {
+            Some(rollout_percentage) => {
+                Ok(hash(&self.key, distinct_id, "") <= rollout_percentage / 100.0)
+            }
+            None => Ok(true),
+        }
+    }
+
+    fn matching_variant(&self, variants: &[FlagVariant], distinct_id: &str) -> Option<String> {
+        let hash = hash(&self.key, distinct_id, "variant");
+        let mut min = 0.0;
+
+        for variant in variants {
+            let max = min + variant.rollout_percentage / 100.0;
+            if hash >= min && hash < max {
+                return Some(variant.key.clone());
+            }
+
+            min = max;
+        }
+
+        None
+    }
+}
+
+impl FlagProperty {
+    fn matches(&self, properties: &HashMap<String, Value>) -> Result<bool, InconclusiveMatch> {
+        // Cohorts and flag dependencies are only known to the server.
+        if matches!(self.kind.as_deref(), Some("cohort") | Some("flag")) {
+            return Err(InconclusiveMatch);
+        }
+
+        let operator = self.operator.as_deref().unwrap_or("exact");
+
+        let Some(actual) = properties.get(&self.key) else {
+            return Err(InconclusiveMatch);
+        };
+
+        let expected = value_to_string(&self.value);
+        let actual_str = value_to_string(actual);
+
+        let exact_match = || match &self.value {
+            Value::Array(values) => values
+                .iter()
+                .any(|value| value_to_string(value).to_lowercase() == actual_str.to_lowercase()),
+            _ => expected.to_lowercase() == actual_str.to_lowercase(),
+        };
+
+        let matched = match operator {
+            "exact" => exact_match(),
+            "is_not" => !exact_match(),
+            "is_set" => true,
+            "is_not_set" => return Err(InconclusiveMatch),
+            "icontains" => actual_str.to_lowercase().contains(&expected.to_lowercase()),
+            "not_icontains" => !actual_str.to_lowercase().contains(&expected.to_lowercase()),
+            "regex" => self
+                .regex(&expected)
+                .is_some_and(|regex| regex.is_match(&actual_str)),
+            "not_regex" => self
+                .regex(&expected)
+                .is_some_and(|regex| !regex.is_match(&actual_str)),
+            "gt" | "gte" | "lt" | "lte" => {
+                let ordering = match (expected.parse::<f64>(), actual_str.parse::<f64>()) {
+                    (Ok(expected), Ok(actual)) => actual.partial_cmp(&expected),
+                    _ => Some(actual_str.cmp(&expected)),
+                };
+
+                let Some(ordering) = ordering else {
+                    return Ok(false);
+                };
+
+                match operator {
+                    "gt" => ordering.is_gt(),
+                    "gte" => ordering.is_ge(),
+                    "lt" => ordering.is_lt(),
+                    _ => ordering.is_le(),
+                }
+            }
+            "is_date_before" | "is_date_after" => {
+                let (Some(expected), Some(actual)) =
+                    (parse_relative_date(&expected), parse_date(&actual_str))
+                else {
+                    return Err(InconclusiveMatch);
+                };
+
+                if operator == "is_date_before" {
+                    actual < expected
+                } else {
+                    actual > expected
+                }
+            }
+            _ => return Err(InconclusiveMatch),
+        };
+
+        Ok(matched)
+    }
+
+    /// Compiles the pattern on first use, it is then reused until the definitions are refreshed.
+    fn regex(&self, pattern: &str) -> Option<&Regex> {
+        self.regex.get_or_init(|| Regex::new(pattern).ok()).as_ref()
+    }
+}
+
+/// Deterministically maps a flag key and distinct ID to a number in `[0, 1]`.
+fn hash(key: &str, distinct_id: &str, salt: &str) -> f64 {
+    let digest = Sha1::digest(format!("{}.{}{}", key, distinct_id, salt).as_bytes());
+    let hex = digest
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+
+    u64::from_str_radix(&hex[..15], 16).unwrap_or_default() as f64 / LONG_SCALE
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.with_timezone(&Utc));
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|date| date.and_utc())
+}
+
+/// Parses either an absolute date or a relative one such as `-7d`, `-2w` or `-1y`.
+fn parse_relative_date(value: &str) -> Option<DateTime<Utc>> {
+    let relative = value.strip_prefix('-').unwrap_or(value);
+    let unit = relative.chars().last()?;
+
+    if let Ok(amount) = relative[..relative.len() - unit.len_utf8()].parse::<i64>() {
+        let duration = match unit {
+            'h' => Duration::try_hours(amount),
+            'd' => Duration::try_days(amount),
+            'w' => Duration::try_weeks(amount),
+            'm' => Duration::try_days(amount.checked_mul(30)?),
+            'y' => Duration::try_days(amount.checked_mul(365)?),
+            _ => None,
+        }?;
+
+        return Utc::now().checked_sub_signed(duration);
+    }
+
+    parse_date(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn definition(value: Value) -> FlagDefinition {
+        serde_json::from_value(value).unwrap()
+    }
+
+    /// Evaluates the flag, returning its variant as a string or `None` if inconclusive.
+    fn evaluate(definition: &FlagDefinition, person: &Person) -> Option<String> {
+        definition
+            .evaluate(person, &HashMap::new())
+            .ok()
+            .map(|flag| flag.variant_as_str())
+    }
+
+    fn person(distinct_id: &str) -> Person {
+        Person::builder().distinct_id(distinct_id).build().unwrap()
+    }
+
+    /// Matches the property filter against a single property, returning `None` if inconclusive.
+    fn matches(operator: &str, expected: Value, actual: Value) -> Option<bool> {
+        let property: FlagProperty = serde_json::from_value(json!({
+            "key": "key",
+            "operator": operator,
+            "value": expected,
+        }))
+        .unwrap();
+
+        property
+            .matches(&HashMap::from([("key".to_string(), actual)]))
+            .ok()
+    }
+
+    // The expected results are taken from the consistency tests of posthog-python.
+    #[test]
+    fn simple_flag_consistency() {
+        let definition = definition(json!({
+            "key": "simple-flag",
+            "active": true,
+            "filters": {
+                "groups": [{ "properties": [], "rollout_percentage": 45 }],
+            },
+        }));
+
+        let expected = [
+            false, true, true, false, true, false, false, true, false, true, false, true, true,
+            false, true, false, false, false, true, true, false, true, false, false, true, false,
+            true, true, false, false, false, true, true, true, true, false, false, false, false,
+            false, false, true, true, false, true, true, false, false, false, true, true, false,
+            false, false, false, true, false, true, false, true, false, true, true, false, true,
+            false, true, false, true, true, false, false, true, false, false, true, false, true,
+            false, false, true, false, false, false, true, true, false, true, true, false, true,
+            true, true, true, true, false, true, true, false, false,
+        ];
+
+        for (i, expected) in expected.into_iter().enumerate() {
+            let person = person(&format!("distinct_id_{}", i));
+
+            assert_eq!(
+                evaluate(&definition, &person),
+                Some(expected.to_string()),
+                "distinct_id_{}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn multivariate_flag_consistency() {
+        let definition = definition(json!({
+            "key": "multivariate-flag",
+            "active": true,
+            "filters": {
+                "groups": [{ "properties": [], "rollout_percentage": 55 }],
+                "multivariate": {
+                    "variants": [
+                        { "key": "first-variant", "rollout_percentage": 50 },
+                        { "key": "second-variant", "rollout_percentage": 20 },
+                        { "key": "third-variant", "rollout_percentage": 20 },
+                        { "key": "fourth-variant", "rollout_percentage": 5 },
+                        { "key": "fifth-variant", "rollout_percentage": 5 },
+                    ],
+                },
+            },
+        }));
+
+        let expected = [
+            Some("second-variant"),
+            Some("second-variant"),
+            Some("first-variant"),
+            None,
+            None,
+            Some("second-variant"),
+            Some("first-variant"),
+            None,
+            None,
+            None,
+            Some("first-variant"),
+            Some("third-variant"),
+            None,
+            Some("first-variant"),
+            Some("second-variant"),
+            Some("first-variant"),
+            None,
+            None,
+            Some("fourth-variant"),
+            Some("first-variant"),
+        ];
+
+        for (i, expected) in expected.into_iter().enumerate() {
+            let person = person(&format!("distinct_id_{}", i));
+            assert_eq!(
+                evaluate(&definition, &person),
+                Some(expected.unwrap_or("false").to_string()),
+                "distinct_id_{}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn property_operators() {
+        assert_eq!(matches("exact", json!("Value"), json!("value")), Some(true));
+        assert_eq!(matches("exact", json!(["a", "b"]), json!("b")), Some(true));
+        assert_eq!(matches("exact", json!(1), json!("1")), Some(true));
+        assert_eq!(matches("is_not", json!(["a", "b"]), json!("c")), Some(true));
+        assert_eq!(matches("is_set", json!("is_set"), json!("")), Some(true));
+        assert_eq!(
+            matches("icontains", json!("OST"), json!("posthog")),
+            Some(true)
+        );
+        assert_eq!(
+            matches("not_icontains", json!("x"), json!("posthog")),
+            Some(true)
+        );
+        assert_eq!(matches("regex", json!(r"^\d+$"), json!("123")), Some(true));
+        assert_eq!(matches("regex", json!("("), json!("(")), Some(false));
+        assert_eq!(
+            matches("not_regex", json!(r"^\d+$"), json!("abc")),
+            Some(true)
+        );
+        assert_eq!(matches("gt", json!(5), json!(10)), Some(true));
+        assert_eq!(matches("gte", json!("5"), json!(5)), Some(true));
+        assert_eq!(matches("lt", json!(5), json!(10)), Some(false));
+        assert_eq!(matches("lte", json!("b"), json!("a")), Some(true));
+        assert_eq!(
+            matches("is_date_before", json!("2024-01-01"), json!("2023-12-31")),
+            Some(true)
+        );
+        assert_eq!(
+            matches("is_date_after", json!("-7d"), json!("2000-01-01T00:00:00Z")),
+            Some(false)
+        );
+        assert_eq!(matches("is_not_set", json!("is_not_set"), json!(1)), None);
+        assert_eq!(matches("unknown", json!(1), json!(1)), None);
+    }
+
+    #[test]
+    fn missing_property_is_inconclusive() {
+        let definition = definition(json!({
+            "key": "flag",
+            "active": true,
+            "filters": {
+                "groups": [{
+                    "properties": [{ "key": "plan", "value": "pro", "type": "person" }],
+                    "rollout_percentage": 100,
+                }],
+            },
+        }));
+
+        assert_eq!(evaluate(&definition, &person("1")), None);
+
+        let person = Person::builder()
+            .distinct_id("1")
+            .property("plan", "pro")
+            .build()
+            .unwrap();
+        assert_eq!(evaluate(&definition, &person), Some("true".to_string()));
+    }
+}
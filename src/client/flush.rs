@@ -0,0 +1,26 @@
+use tokio::time::Duration;
+
+use crate::error::PosthogError;
+
+use super::{queue::FlushReport, PosthogClient};
+
+impl PosthogClient {
+    /// Sends every queued event and waits for all in-flight requests to complete.
+    ///
+    /// The report only counts the events this call sent.
+    pub async fn flush(&self) -> Result<FlushReport, PosthogError> {
+        self.queue.flush().await
+    }
+
+    /// Flushes the queue and stops the background batch worker.
+    ///
+    /// Events enqueued after the shutdown are dropped. If the queue could not be drained within
+    /// `timeout`, the events still being sent are reported as pending.
+    pub async fn shutdown(&self, timeout: Duration) -> Result<FlushReport, PosthogError> {
+        if let Some(local_evaluator) = &self.local_evaluator {
+            local_evaluator.stop();
+        }
+
+        self.queue.shutdown(timeout).await
+    }
+}
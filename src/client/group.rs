@@ -0,0 +1,93 @@
+use crate::{
+    data::{Event, Group, Person},
+    error::PosthogError,
+};
+
+use super::PosthogClient;
+
+impl PosthogClient {
+    /// Sets the properties of a group.
+    pub fn enqueue_group_identify(&self, group: &Group) -> Result<(), PosthogError> {
+        let person = Person::builder()
+            .distinct_id(format!("${}_{}", group.group_type, group.group_key))
+            .build()?;
+
+        Event::builder()
+            .name("$groupidentify")
+            .process_person_profile(true)
+            .property("$group_type", group.group_type.clone())
+            .property("$group_key", group.group_key.clone())
+            .property(
+                "$group_set",
+                serde_json::to_value(group.properties.clone().unwrap_or_default())?,
+            )
+            .build()?
+            .enqueue(&person, self)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        data::{Event, Group, Person},
+        testing::Recorder,
+    };
+
+    #[tokio::test]
+    async fn group_identify_sets_group_properties() {
+        let recorder = Recorder::new();
+        let client = recorder.client().unwrap();
+        let group = Group::builder()
+            .group_type("company")
+            .group_key("acme")
+            .property("employees", 42)
+            .build()
+            .unwrap();
+
+        client.enqueue_group_identify(&group).unwrap();
+
+        let event = recorder.assert_captured("$groupidentify");
+        assert_eq!(event.distinct_id(), "$company_acme");
+        assert_eq!(event.property("$group_type"), Some(&json!("company")));
+        assert_eq!(event.property("$group_key"), Some(&json!("acme")));
+        assert_eq!(
+            event.property("$group_set"),
+            Some(&json!({ "employees": 42 }))
+        );
+    }
+
+    #[tokio::test]
+    async fn events_include_their_groups() {
+        let recorder = Recorder::new();
+        let client = recorder.client().unwrap();
+        let person = Person::builder()
+            .distinct_id("user-1")
+            .group(
+                Group::builder()
+                    .group_type("company")
+                    .group_key("acme")
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+
+        Event::builder()
+            .name("a")
+            .group("project", "website")
+            .build()
+            .unwrap()
+            .enqueue(&person, &client)
+            .unwrap();
+
+        let event = recorder.assert_captured("a");
+        assert_eq!(
+            event.property("$groups"),
+            Some(&json!({ "company": "acme", "project": "website" }))
+        );
+    }
+}
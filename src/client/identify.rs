@@ -1,3 +1,7 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
 use crate::{
     data::{Event, Person},
     error::PosthogError,
@@ -9,9 +13,135 @@ impl PosthogClient {
     pub fn enqueue_identify(&self, person: &Person) -> Result<(), PosthogError> {
         Event::builder()
             .name("$identify")
+            .process_person_profile(true)
+            .build()?
+            .enqueue(person, self)?;
+
+        Ok(())
+    }
+
+    /// Updates the person's properties in a single `$set` event: `set` overwrites properties,
+    /// `set_once` only sets properties the person does not have yet, and `unset` removes properties.
+    pub fn enqueue_set_person_properties(
+        &self,
+        person: &Person,
+        set: HashMap<String, Value>,
+        set_once: HashMap<String, Value>,
+        unset: Vec<String>,
+    ) -> Result<(), PosthogError> {
+        let mut event = Event::builder().name("$set").process_person_profile(true);
+
+        if !set.is_empty() {
+            event = event.property("$set", json!(set));
+        }
+
+        if !set_once.is_empty() {
+            event = event.property("$set_once", json!(set_once));
+        }
+
+        if !unset.is_empty() {
+            event = event.property("$unset", json!(unset));
+        }
+
+        event.build()?.enqueue(person, self)?;
+
+        Ok(())
+    }
+
+    /// Links another distinct ID, such as an anonymous one, to the person.
+    pub fn enqueue_alias(
+        &self,
+        person: &Person,
+        alias: impl Into<String>,
+    ) -> Result<(), PosthogError> {
+        Event::builder()
+            .name("$create_alias")
+            .process_person_profile(true)
+            .property("distinct_id", person.distinct_id.clone())
+            .property("alias", alias.into())
             .build()?
             .enqueue(person, self)?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use crate::{data::Person, testing::Recorder};
+
+    use super::PosthogClient;
+
+    fn client(recorder: &Recorder) -> PosthogClient {
+        PosthogClient::builder()
+            .base_url("http://localhost")
+            .api_key("test-api-key")
+            .recorder(recorder.clone())
+            .process_person_profile(false)
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn identify_sets_person_properties() {
+        let recorder = Recorder::new();
+        let mut person = Person::builder()
+            .distinct_id("user-1")
+            .property("plan", "pro")
+            .property_once("signed_up_at", "2024-01-01")
+            .build()
+            .unwrap();
+        person.unset_property("trial");
+
+        client(&recorder).enqueue_identify(&person).unwrap();
+
+        let event = recorder.assert_captured("$identify");
+        assert_eq!(event.property("$set"), Some(&json!({ "plan": "pro" })));
+        assert_eq!(
+            event.property("$set_once"),
+            Some(&json!({ "signed_up_at": "2024-01-01" }))
+        );
+        assert_eq!(event.property("$unset"), Some(&json!(["trial"])));
+        assert_eq!(event.property("$process_person_profile"), None);
+    }
+
+    #[tokio::test]
+    async fn set_person_properties() {
+        let recorder = Recorder::new();
+        let person = Person::builder().distinct_id("user-1").build().unwrap();
+
+        client(&recorder)
+            .enqueue_set_person_properties(
+                &person,
+                HashMap::from([("plan".to_string(), json!("pro"))]),
+                HashMap::new(),
+                vec!["trial".to_string()],
+            )
+            .unwrap();
+
+        let event = recorder.assert_captured("$set");
+        assert_eq!(event.property("$set"), Some(&json!({ "plan": "pro" })));
+        assert_eq!(event.property("$set_once"), None);
+        assert_eq!(event.property("$unset"), Some(&json!(["trial"])));
+    }
+
+    #[tokio::test]
+    async fn alias_links_distinct_ids() {
+        let recorder = Recorder::new();
+        let person = Person::builder().distinct_id("user-1").build().unwrap();
+
+        client(&recorder)
+            .enqueue_alias(&person, "anonymous-1")
+            .unwrap();
+
+        let event = recorder.assert_captured("$create_alias");
+        assert_eq!(event.distinct_id(), "user-1");
+        assert_eq!(event.property("distinct_id"), Some(&json!("user-1")));
+        assert_eq!(event.property("alias"), Some(&json!("anonymous-1")));
+        assert_eq!(event.property("$process_person_profile"), None);
+    }
+}
@@ -0,0 +1,41 @@
+use chrono::Utc;
+
+use crate::{
+    data::{Event, Person},
+    error::PosthogError,
+};
+
+use super::{FlushReport, PosthogClient};
+
+impl PosthogClient {
+    /// Imports historical events, such as ones migrated from another analytics tool.
+    ///
+    /// The events are sorted by timestamp and sent in large batches flagged as a historical
+    /// migration, which Posthog ingests without applying the rate limits of live events. Events
+    /// without a timestamp are treated as happening now. Batches are sent one after another, and
+    /// the import stops at the first batch that could not be delivered.
+    ///
+    /// In that case [`PosthogError::ImportFailed`] is returned, with a report of the events that
+    /// were delivered. Since batches are sent in timestamp order, these are the earliest events,
+    /// not counting events too large to be sent, so the import can be resumed after them.
+    pub async fn import_events<'a>(
+        &self,
+        events: impl IntoIterator<Item = (&'a Person, Event)>,
+    ) -> Result<FlushReport, PosthogError> {
+        let mut events = events
+            .into_iter()
+            .map(|(person, mut event)| {
+                let timestamp = *event.timestamp.get_or_insert_with(Utc::now);
+                (timestamp, self.get_event_json(person, event))
+            })
+            .collect::<Vec<_>>();
+
+        events.sort_by_key(|(timestamp, _)| *timestamp);
+
+        let events = events.into_iter().map(|(_, json)| json).collect();
+
+        self.queue
+            .import(&self.api_key, events, self.import_batch_size)
+            .await
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use tokio::{
+    select,
+    sync::{oneshot::channel, watch},
+    time::{sleep, Duration},
+};
+
+use crate::{
+    data::{FeatureFlag, Person},
+    error::PosthogError,
+};
+
+use super::{
+    flag_matching::FlagDefinitions,
+    queue::{PosthogRequest::GetFeatureFlagDefinitions, QueueWorker, QueuedRequest},
+};
+
+/// Settings used to enable local evaluation.
+#[derive(Debug)]
+pub(crate) struct LocalEvaluationConfig {
+    pub(crate) personal_api_key: String,
+    pub(crate) polling_interval: Duration,
+}
+
+/// Evaluates feature flags in-process using definitions fetched with a personal API key.
+///
+/// The definitions are refreshed periodically in the background until the client is shut down.
+#[derive(Debug, Clone)]
+pub(crate) struct LocalEvaluator {
+    definitions: Arc<RwLock<Option<FlagDefinitions>>>,
+    stop_tx: Arc<watch::Sender<bool>>,
+}
+
+/// The result of evaluating every known flag locally.
+pub(crate) struct LocalEvaluation {
+    pub(crate) flags: HashMap<String, FeatureFlag>,
+    /// Whether some flags could not be evaluated locally.
+    pub(crate) is_inconclusive: bool,
+}
+
+impl LocalEvaluator {
+    pub(crate) fn start(
+        queue: QueueWorker,
+        api_key: String,
+        personal_api_key: String,
+        polling_interval: Duration,
+    ) -> Self {
+        let (stop_tx, mut stop_rx) = watch::channel(false);
+
+        let evaluator = Self {
+            definitions: Arc::default(),
+            stop_tx: Arc::new(stop_tx),
+        };
+
+        {
+            let definitions = evaluator.definitions.clone();
+            let runtime = queue.runtime().clone();
+
+            runtime.spawn(async move {
+                loop {
+                    // Keep the previous definitions if they could not be refreshed.
+                    if let Ok(fetched) =
+                        Self::fetch_definitions(&queue, &api_key, &personal_api_key).await
+                    {
+                        *definitions.write().unwrap() = Some(fetched);
+                    }
+
+                    select! {
+                        _ = sleep(polling_interval) => {}
+                        _ = stop_rx.changed() => break,
+                    }
+                }
+            });
+        }
+
+        evaluator
+    }
+
+    async fn fetch_definitions(
+        queue: &QueueWorker,
+        api_key: &str,
+        personal_api_key: &str,
+    ) -> Result<FlagDefinitions, PosthogError> {
+        let (tx, rx) = channel();
+
+        queue.offer(QueuedRequest {
+            request: GetFeatureFlagDefinitions {
+                api_key: api_key.to_string(),
+                personal_api_key: personal_api_key.to_string(),
+            },
+            response_tx: Some(tx),
+            ..Default::default()
+        })?;
+
+        let json = rx
+            .await
+            .map_err(|error| PosthogError::QueueError(error.into()))??;
+        let json = serde_json::from_value::<FlagDefinitions>(json)?;
+
+        Ok(json)
+    }
+
+    /// Evaluates every known flag for the person.
+    ///
+    /// Returns `None` if the definitions have not been loaded yet.
+    pub(crate) fn evaluate(&self, person: &Person) -> Option<LocalEvaluation> {
+        let definitions = self.definitions.read().unwrap();
+        let definitions = definitions.as_ref()?;
+
+        let (flags, inconclusive) = definitions.evaluate(person);
+
+        Some(LocalEvaluation {
+            flags,
+            is_inconclusive: !inconclusive.is_empty(),
+        })
+    }
+
+    /// Stops refreshing the definitions.
+    pub(crate) fn stop(&self) {
+        self.stop_tx.send(true).ok();
+    }
+}
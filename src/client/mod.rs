@@ -1,19 +1,77 @@
+mod buffer;
 mod builder;
 mod early_access;
 mod event;
+mod exception;
 mod feature_flag;
+mod flag_cache;
+mod flag_called;
+mod flag_matching;
+mod flush;
+mod group;
 mod identify;
+mod import;
+mod local_evaluation;
 mod queue;
+mod retry;
+mod spool;
+mod super_properties;
+mod transport;
 mod view;
 
+pub use buffer::QueueFullPolicy;
 pub use builder::PosthogClientBuilder;
+pub use queue::{FlushReport, PosthogRequest};
+pub use retry::RetryPolicy;
+pub use transport::{ReqwestTransport, Transport, TransportRequest, TransportResponse};
 
-use self::queue::QueueWorker;
+#[cfg(any(test, feature = "testing"))]
+pub(crate) use queue::QueuedRequest;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use serde_json::Value;
+
+use crate::data::FeatureFlag;
+
+use self::{
+    flag_cache::{FeatureFlagCache, FeatureFlagCacheConfig},
+    flag_called::FeatureFlagCalledCache,
+    local_evaluation::{LocalEvaluationConfig, LocalEvaluator},
+    queue::{QueueConfig, QueueWorker},
+};
 
 #[derive(Debug, Clone)]
 pub struct PosthogClient {
     pub(crate) api_key: String,
     pub(crate) queue: QueueWorker,
+    pub(crate) local_evaluator: Option<LocalEvaluator>,
+    pub(crate) feature_flag_called_cache: Arc<Mutex<FeatureFlagCalledCache>>,
+    pub(crate) feature_flag_cache: Option<Arc<Mutex<FeatureFlagCache>>>,
+    pub(crate) super_properties: Arc<RwLock<HashMap<String, Value>>>,
+    pub(crate) lib_name: String,
+    pub(crate) lib_version: String,
+    pub(crate) import_batch_size: usize,
+    pub(crate) process_person_profile: bool,
+    pub(crate) flag_overrides: Arc<HashMap<String, FeatureFlag>>,
+}
+
+/// Settings used to construct the client.
+#[derive(Debug)]
+pub(crate) struct ClientConfig {
+    pub(crate) api_key: String,
+    pub(crate) local_evaluation: Option<LocalEvaluationConfig>,
+    pub(crate) feature_flag_called_cache_size: usize,
+    pub(crate) feature_flag_cache: Option<FeatureFlagCacheConfig>,
+    pub(crate) super_properties: HashMap<String, Value>,
+    pub(crate) lib_name: String,
+    pub(crate) lib_version: String,
+    pub(crate) import_batch_size: usize,
+    pub(crate) process_person_profile: bool,
+    pub(crate) flag_overrides: HashMap<String, FeatureFlag>,
 }
 
 impl PosthogClient {
@@ -21,10 +79,34 @@ impl PosthogClient {
         PosthogClientBuilder::new()
     }
 
-    pub(crate) fn new(base_url: String, api_key: String) -> Self {
+    pub(crate) fn new(config: ClientConfig, queue_config: QueueConfig) -> Self {
+        let queue = QueueWorker::new(queue_config);
+
+        let local_evaluator = config.local_evaluation.map(|local_evaluation| {
+            LocalEvaluator::start(
+                queue.clone(),
+                config.api_key.clone(),
+                local_evaluation.personal_api_key,
+                local_evaluation.polling_interval,
+            )
+        });
+
         Self {
-            api_key,
-            queue: QueueWorker::new(base_url),
+            api_key: config.api_key,
+            queue,
+            local_evaluator,
+            feature_flag_called_cache: Arc::new(Mutex::new(FeatureFlagCalledCache::new(
+                config.feature_flag_called_cache_size,
+            ))),
+            feature_flag_cache: config
+                .feature_flag_cache
+                .map(|config| Arc::new(Mutex::new(FeatureFlagCache::new(config)))),
+            super_properties: Arc::new(RwLock::new(config.super_properties)),
+            lib_name: config.lib_name,
+            lib_version: config.lib_version,
+            import_batch_size: config.import_batch_size,
+            process_person_profile: config.process_person_profile,
+            flag_overrides: Arc::new(config.flag_overrides),
         }
     }
 }
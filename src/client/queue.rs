@@ -1,17 +1,39 @@
-use reqwest::{Client, Method};
+use std::{
+    future::Future,
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use reqwest::{
+    header::{HeaderMap, HeaderValue, AUTHORIZATION, USER_AGENT},
+    Method,
+};
 use serde_json::{json, Value};
 use tokio::{
+    runtime::Handle,
     select,
     sync::{
         mpsc::{unbounded_channel, UnboundedSender},
-        oneshot::Sender,
+        oneshot::{self, Sender},
+        Notify,
     },
+    task::spawn_blocking,
     time::{interval, Duration},
 };
 
 use crate::error::PosthogError;
 
-#[derive(Debug)]
+use super::{
+    buffer::{EventBuffer, PushError, QueueFullPolicy},
+    retry::{retry_after, RetryPolicy},
+    spool::{Spool, SpoolSegment},
+    transport::{Transport, TransportRequest},
+};
+
+#[derive(Debug, Clone)]
 pub enum PosthogRequest {
     /// Capture an event.
     ///
@@ -37,6 +59,15 @@ pub enum PosthogRequest {
     /// Method: GET
     GetEarlyAccessFeatures { api_key: String },
 
+    /// Get feature flag definitions for local evaluation.
+    ///
+    /// Endpoint: /api/feature_flag/local_evaluation
+    /// Method: GET
+    GetFeatureFlagDefinitions {
+        api_key: String,
+        personal_api_key: String,
+    },
+
     /// Any other request.
     ///
     /// Endpoint: Any
@@ -48,6 +79,19 @@ pub enum PosthogRequest {
     },
 }
 
+impl PosthogRequest {
+    /// The number of events carried by this request.
+    fn event_count(&self) -> usize {
+        match self {
+            PosthogRequest::CaptureEvent { .. } => 1,
+            PosthogRequest::CaptureBatch { body } => {
+                body["batch"].as_array().map(Vec::len).unwrap_or(0)
+            }
+            _ => 0,
+        }
+    }
+}
+
 impl Default for PosthogRequest {
     fn default() -> Self {
         Self::Other {
@@ -62,70 +106,191 @@ impl Default for PosthogRequest {
 pub(crate) struct QueuedRequest {
     pub(crate) request: PosthogRequest,
     pub(crate) response_tx: Option<Sender<Result<Value, PosthogError>>>,
+    /// The spool segment holding this batch, removed once the batch is delivered.
+    pub(crate) spool_segment: Option<SpoolSegment>,
+    /// The flush or shutdown that sent this batch.
+    pub(crate) tally: Option<Arc<FlushTally>>,
+}
+
+/// Summary of the events sent while flushing or shutting down the queue.
+///
+/// Only the batches sent by that call are counted. Batches sent earlier by the background worker,
+/// or by a concurrent flush, are not part of the report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlushReport {
+    /// Events that were accepted by the Posthog API.
+    pub delivered: usize,
+    /// Events that could not be delivered, and were discarded.
+    pub dropped: usize,
+    /// Events that could not be delivered, and were kept in the spool. They are sent again the next
+    /// time a client is built with the same spool directory.
+    pub spooled: usize,
+    /// Events that were still being sent when the shutdown timeout elapsed.
+    pub pending: usize,
+}
+
+/// Counts the events of the batches sent by a single flush or shutdown.
+#[derive(Debug, Default)]
+pub(crate) struct FlushTally {
+    pending: AtomicUsize,
+    delivered: AtomicUsize,
+    dropped: AtomicUsize,
+    spooled: AtomicUsize,
+}
+
+impl FlushTally {
+    fn report(&self) -> FlushReport {
+        FlushReport {
+            delivered: self.delivered.load(Ordering::SeqCst),
+            dropped: self.dropped.load(Ordering::SeqCst),
+            spooled: self.spooled.load(Ordering::SeqCst),
+            pending: self.pending.load(Ordering::SeqCst),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum QueueCommand {
+    /// Send every pending event, then notify the caller.
+    Flush {
+        tally: Arc<FlushTally>,
+        done_tx: oneshot::Sender<()>,
+    },
+
+    /// Send every pending event, notify the caller and stop the batch worker.
+    Shutdown {
+        tally: Arc<FlushTally>,
+        done_tx: oneshot::Sender<()>,
+    },
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+impl QueueState {
+    async fn wait_idle(&self) {
+        loop {
+            let notified = self.idle.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if self.in_flight.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+
+            notified.await;
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub(crate) struct QueueWorker {
     client: QueueClient,
-    batch_capture_tx: UnboundedSender<Value>,
+    buffer: Arc<EventBuffer>,
+    queue_full_policy: QueueFullPolicy,
+    max_batch_size: usize,
+    max_batch_bytes: usize,
+    spool: Option<Arc<Spool>>,
+    runtime: Handle,
+    #[cfg(any(test, feature = "testing"))]
+    recorder: Option<crate::testing::Recorder>,
+    command_tx: UnboundedSender<QueueCommand>,
+    state: Arc<QueueState>,
 }
 
 #[derive(Clone, Debug)]
 struct QueueClient {
     base_url: String,
-    client: Client,
+    transport: Arc<dyn Transport>,
+    retry_policy: RetryPolicy,
+    user_agent: Option<HeaderValue>,
+}
+
+/// Settings used to construct the queue worker.
+#[derive(Debug)]
+pub(crate) struct QueueConfig {
+    pub(crate) base_url: String,
+    pub(crate) transport: Arc<dyn Transport>,
+    pub(crate) user_agent: String,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) max_queued_events: usize,
+    pub(crate) queue_full_policy: QueueFullPolicy,
+    pub(crate) flush_interval: Duration,
+    pub(crate) max_batch_size: usize,
+    pub(crate) max_batch_bytes: usize,
+    pub(crate) spool: Option<Spool>,
+    pub(crate) runtime: Handle,
+    #[cfg(any(test, feature = "testing"))]
+    pub(crate) recorder: Option<crate::testing::Recorder>,
 }
 
 impl QueueWorker {
-    pub(crate) fn new(base_url: String) -> Self {
+    pub(crate) fn new(config: QueueConfig) -> Self {
         let client = QueueClient {
-            base_url: base_url.clone(),
-            client: Client::new(),
+            base_url: config.base_url,
+            transport: config.transport,
+            retry_policy: config.retry_policy,
+            user_agent: HeaderValue::from_str(&config.user_agent).ok(),
         };
 
-        let (batch_capture_tx, mut rx) = unbounded_channel::<Value>();
+        let (command_tx, mut command_rx) = unbounded_channel::<QueueCommand>();
 
         let worker = Self {
             client,
-            batch_capture_tx,
+            buffer: Arc::new(EventBuffer::new(
+                config.max_queued_events,
+                config.max_batch_size,
+            )),
+            queue_full_policy: config.queue_full_policy,
+            max_batch_size: config.max_batch_size.max(1),
+            max_batch_bytes: config.max_batch_bytes,
+            spool: config.spool.map(Arc::new),
+            runtime: config.runtime,
+            #[cfg(any(test, feature = "testing"))]
+            recorder: config.recorder,
+            command_tx,
+            state: Arc::default(),
         };
 
         // Only capture events can be batched, everything else will be executed immediately.
         {
             let worker = worker.clone();
 
-            tokio::spawn(async move {
-                let mut events = vec![];
-                let mut flush_timer = interval(Duration::from_secs(1));
+            worker.runtime.clone().spawn(async move {
+                worker.replay_spool().await;
+
+                // The timer panics on a zero period.
+                let mut flush_timer = interval(config.flush_interval.max(Duration::from_millis(1)));
 
                 loop {
                     select! {
-                        Some(event) = rx.recv() => {
-                            events.push(event);
-                        }
+                        Some(command) = command_rx.recv() => {
+                            match command {
+                                QueueCommand::Flush { tally, done_tx } => {
+                                    worker.dispatch_batch(Some(&tally));
+                                    done_tx.send(()).ok();
+                                }
 
-                        _ = flush_timer.tick() => {
-                            if events.is_empty() {
-                                continue;
+                                QueueCommand::Shutdown { tally, done_tx } => {
+                                    worker.dispatch_batch(Some(&tally));
+                                    worker.buffer.close();
+                                    worker.dispatch_batch(Some(&tally));
+                                    done_tx.send(()).ok();
+                                    break;
+                                }
                             }
+                        }
 
-                            let mut batch = vec![];
-                            std::mem::swap(&mut events, &mut batch);
-
-                            // The API key is added by the client to each event, so we can just take it from the first event.
-                            let api_key = batch[0]["api_key"].as_str().unwrap();
-
-                            let body = json!({
-                                "api_key": api_key,
-                                "batch": batch,
-                            });
-
-                            let request = QueuedRequest {
-                                request: PosthogRequest::CaptureBatch { body },
-                                response_tx: None,
-                            };
+                        _ = flush_timer.tick() => {
+                            worker.dispatch_batch(None);
+                        }
 
-                            worker.dispatch_request(request);
+                        _ = worker.buffer.batch_ready() => {
+                            worker.dispatch_batch(None);
+                            flush_timer.reset();
                         }
                     }
                 }
@@ -135,26 +300,495 @@ impl QueueWorker {
         worker
     }
 
-    pub fn offer(&self, request: QueuedRequest) {
+    pub fn offer(&self, request: QueuedRequest) -> Result<(), PosthogError> {
+        #[cfg(any(test, feature = "testing"))]
+        if let Some(recorder) = &self.recorder {
+            recorder.record(request);
+            return Ok(());
+        }
+
         match request.request {
             PosthogRequest::CaptureEvent { body } => {
-                self.batch_capture_tx.send(body).ok();
+                let result = match self.queue_full_policy {
+                    QueueFullPolicy::DropOldest => {
+                        self.buffer.push_evicting(body).map(|_evicted| {
+                            #[cfg(feature = "tracing")]
+                            if _evicted {
+                                tracing::warn!("queue is full, dropped the oldest event");
+                            }
+                        })
+                    }
+
+                    _ => self.buffer.push(body),
+                };
+
+                self.handle_push_result(result)
             }
 
             _ => {
                 self.dispatch_request(request);
+                Ok(())
+            }
+        }
+    }
+
+    /// Like [`QueueWorker::offer`], but waits for room in the queue when the policy is
+    /// [`QueueFullPolicy::WaitAsync`].
+    pub async fn offer_wait(&self, request: QueuedRequest) -> Result<(), PosthogError> {
+        #[cfg(any(test, feature = "testing"))]
+        if let Some(recorder) = &self.recorder {
+            recorder.record(request);
+            return Ok(());
+        }
+
+        match request.request {
+            PosthogRequest::CaptureEvent { body }
+                if self.queue_full_policy == QueueFullPolicy::WaitAsync =>
+            {
+                let result = self.buffer.push_wait(body).await;
+                self.handle_push_result(result)
+            }
+
+            _ => self.offer(request),
+        }
+    }
+
+    fn handle_push_result(&self, result: Result<(), PushError>) -> Result<(), PosthogError> {
+        match result {
+            Ok(()) => Ok(()),
+
+            Err(PushError::Full(_)) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(policy = ?self.queue_full_policy, "queue is full, dropped an event");
+
+                match self.queue_full_policy {
+                    QueueFullPolicy::DropNewest | QueueFullPolicy::DropOldest => Ok(()),
+                    QueueFullPolicy::WaitAsync | QueueFullPolicy::Error => {
+                        Err(PosthogError::QueueFull)
+                    }
+                }
+            }
+
+            Err(PushError::Closed) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("queue is shut down, dropped an event");
+
+                Err(PosthogError::QueueError("the queue is shut down".into()))
             }
         }
     }
 
     pub fn dispatch_request(&self, request: QueuedRequest) {
+        #[cfg(any(test, feature = "testing"))]
+        if let Some(recorder) = &self.recorder {
+            recorder.record(request);
+            return;
+        }
+
         let client = self.client.clone();
-        tokio::spawn(async move {
-            QueueWorker::handle_request(client, request).await;
+        self.spawn_request(QueueWorker::handle_request(client, request));
+    }
+
+    /// Spawns a request task that flushes wait for.
+    fn spawn_request(&self, task: impl Future<Output = ()> + Send + 'static) {
+        let state = self.state.clone();
+
+        state.in_flight.fetch_add(1, Ordering::SeqCst);
+
+        self.runtime.spawn(async move {
+            task.await;
+
+            if state.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+                state.idle.notify_waiters();
+            }
+        });
+    }
+
+    /// The runtime the background tasks are spawned on.
+    pub(crate) fn runtime(&self) -> &Handle {
+        &self.runtime
+    }
+
+    #[cfg(feature = "blocking")]
+    pub(crate) fn queue_full_policy(&self) -> QueueFullPolicy {
+        self.queue_full_policy
+    }
+
+    /// Sends every pending event and waits until all in-flight requests have completed.
+    pub(crate) async fn flush(&self) -> Result<FlushReport, PosthogError> {
+        let tally = Arc::new(FlushTally::default());
+
+        let (done_tx, done_rx) = oneshot::channel();
+        self.command_tx
+            .send(QueueCommand::Flush {
+                tally: tally.clone(),
+                done_tx,
+            })
+            .map_err(|_| PosthogError::QueueError("the queue worker has stopped".into()))?;
+        done_rx
+            .await
+            .map_err(|error| PosthogError::QueueError(error.into()))?;
+
+        self.state.wait_idle().await;
+
+        Ok(tally.report())
+    }
+
+    /// Sends every pending event and stops the batch worker.
+    ///
+    /// Events that are still being sent when the timeout elapses are reported as pending.
+    pub(crate) async fn shutdown(&self, timeout: Duration) -> Result<FlushReport, PosthogError> {
+        let tally = Arc::new(FlushTally::default());
+
+        let (done_tx, done_rx) = oneshot::channel();
+        self.command_tx
+            .send(QueueCommand::Shutdown {
+                tally: tally.clone(),
+                done_tx,
+            })
+            .map_err(|_| PosthogError::QueueError("the queue worker has stopped".into()))?;
+
+        let state = self.state.clone();
+
+        // The timer runs on the client's runtime, so the caller doesn't need to be on one.
+        let is_dispatched = self
+            .runtime
+            .spawn(async move {
+                let mut is_dispatched = false;
+
+                tokio::time::timeout(timeout, async {
+                    is_dispatched = done_rx.await.is_ok();
+                    state.wait_idle().await;
+                })
+                .await
+                .ok();
+
+                is_dispatched
+            })
+            .await
+            .unwrap_or_default();
+
+        let mut report = tally.report();
+
+        // The worker may still be busy, for example replaying the spool, in which case the queued
+        // events are sent once it gets to the shutdown.
+        if !is_dispatched {
+            report.pending += self.buffer.len();
+        }
+
+        Ok(report)
+    }
+
+    fn dispatch_batch(&self, tally: Option<&Arc<FlushTally>>) {
+        self.dispatch_events(self.buffer.take_all(), tally);
+    }
+
+    /// Sends the batches left in the spool by a previous run.
+    async fn replay_spool(&self) {
+        let Some(spool) = self.spool.clone() else {
+            return;
+        };
+
+        let segments = {
+            let spool = spool.clone();
+
+            spawn_blocking(move || {
+                spool
+                    .segments()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|mut segment| {
+                        let events = segment.read();
+                        (segment, events)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .await
+            .unwrap_or_default()
+        };
+
+        #[cfg(feature = "tracing")]
+        if !segments.is_empty() {
+            tracing::info!(segments = segments.len(), "replaying spooled batches");
+        }
+
+        for (segment, events) in segments {
+            let (envelope, mut batches) = self.split_events(events, None);
+
+            // A segment that still fits in a single batch is sent as it is.
+            if batches.len() <= 1 {
+                match batches.pop() {
+                    Some(batch) => self.dispatch_request(QueueWorker::batch_request(
+                        &envelope,
+                        batch,
+                        Some(segment),
+                        None,
+                    )),
+                    None => {
+                        spawn_blocking(move || segment.remove());
+                    }
+                }
+
+                continue;
+            }
+
+            // Otherwise the events are written to new segments before the old one is removed, so
+            // they are never missing from the spool. If some of them could not be written, the old
+            // segment is kept and replayed again by the next run.
+            let mut requests = vec![];
+            let mut is_respooled = true;
+
+            for batch in batches {
+                let spool_segment = QueueWorker::spool_batch(spool.clone(), &batch).await;
+                is_respooled &= spool_segment.is_some();
+
+                requests.push(QueueWorker::batch_request(
+                    &envelope,
+                    batch,
+                    spool_segment,
+                    None,
+                ));
+            }
+
+            if is_respooled {
+                spawn_blocking(move || segment.remove());
+            }
+
+            for request in requests {
+                self.dispatch_request(request);
+            }
+        }
+    }
+
+    /// Sends the events in one or more batches, persisting each one to the spool first if there
+    /// is one. `tally` is the flush or shutdown the events are sent for, if any.
+    fn dispatch_events(&self, events: Vec<Value>, tally: Option<&Arc<FlushTally>>) {
+        let (envelope, batches) = self.split_events(events, tally);
+
+        #[cfg(feature = "tracing")]
+        if !batches.is_empty() {
+            tracing::debug!(
+                events = batches.iter().map(Vec::len).sum::<usize>(),
+                batches = batches.len(),
+                "flushing queued events"
+            );
+        }
+
+        for batch in batches {
+            let Some(spool) = self.spool.clone() else {
+                self.dispatch_request(QueueWorker::batch_request(&envelope, batch, None, tally));
+                continue;
+            };
+
+            // The batch is written to the spool within its request task, so the disk IO neither
+            // delays the worker nor the other batches.
+            let client = self.client.clone();
+            let contents = Spool::encode(&batch);
+            let mut request = QueueWorker::batch_request(&envelope, batch, None, tally);
+
+            self.spawn_request(async move {
+                request.spool_segment = QueueWorker::spool_contents(spool, contents).await;
+                QueueWorker::handle_request(client, request).await;
+            });
+        }
+    }
+
+    /// Splits queued or spooled events into batches, dropping the events that are too large to be
+    /// sent on their own. Returns the envelope the batches are sent in along with the batches.
+    fn split_events(
+        &self,
+        events: Vec<Value>,
+        tally: Option<&Arc<FlushTally>>,
+    ) -> (Value, Vec<Vec<Value>>) {
+        if events.is_empty() {
+            return (Value::Null, vec![]);
+        }
+
+        // The API key is added by the client to each event, so we can just take it from the first event.
+        let api_key = events[0]["api_key"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let envelope = json!({ "api_key": api_key });
+
+        let (batches, oversized) = self.split_batch(&envelope, events, self.max_batch_size);
+
+        if let Some(tally) = tally {
+            tally.dropped.fetch_add(oversized, Ordering::SeqCst);
+        }
+
+        #[cfg(feature = "tracing")]
+        if oversized > 0 {
+            tracing::warn!(
+                dropped = oversized,
+                max_batch_bytes = self.max_batch_bytes,
+                "dropped events larger than the maximum batch size"
+            );
+        }
+
+        (envelope, batches)
+    }
+
+    fn batch_request(
+        envelope: &Value,
+        batch: Vec<Value>,
+        spool_segment: Option<SpoolSegment>,
+        tally: Option<&Arc<FlushTally>>,
+    ) -> QueuedRequest {
+        if let Some(tally) = tally {
+            tally.pending.fetch_add(batch.len(), Ordering::SeqCst);
+        }
+
+        let mut body = envelope.clone();
+        body["batch"] = json!(batch);
+
+        QueuedRequest {
+            request: PosthogRequest::CaptureBatch { body },
+            response_tx: None,
+            spool_segment,
+            tally: tally.cloned(),
+        }
+    }
+
+    async fn spool_batch(spool: Arc<Spool>, batch: &[Value]) -> Option<SpoolSegment> {
+        QueueWorker::spool_contents(spool, Spool::encode(batch)).await
+    }
+
+    /// Writes a batch encoded with [`Spool::encode`] to the spool on a blocking thread.
+    async fn spool_contents(spool: Arc<Spool>, contents: Vec<u8>) -> Option<SpoolSegment> {
+        let result = match spawn_blocking(move || spool.write(&contents)).await {
+            Ok(result) => result,
+            Err(error) => Err(io::Error::other(error)),
+        };
+
+        result
+            .inspect_err(|_error| {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(error = %_error, "failed to spool batch");
+            })
+            .ok()
+            .flatten()
+    }
+
+    /// Sends events in order, waiting for each batch to be accepted before sending the next one.
+    ///
+    /// The batches are marked as a historical migration, which Posthog ingests separately from
+    /// live events. Stops at the first batch that could not be delivered, and returns
+    /// [`PosthogError::ImportFailed`] with the events that were not sent reported as pending.
+    pub(crate) async fn import(
+        &self,
+        api_key: &str,
+        events: Vec<Value>,
+        batch_size: usize,
+    ) -> Result<FlushReport, PosthogError> {
+        let envelope = json!({
+            "api_key": api_key,
+            "historical_migration": true,
         });
+
+        let (batches, oversized) = self.split_batch(&envelope, events, batch_size.max(1));
+
+        let mut report = FlushReport {
+            dropped: oversized,
+            ..Default::default()
+        };
+
+        let mut batches = batches.into_iter();
+
+        while let Some(batch) = batches.next() {
+            let event_count = batch.len();
+
+            let mut body = envelope.clone();
+            body["batch"] = json!(batch);
+
+            let (tx, rx) = oneshot::channel();
+
+            self.dispatch_request(QueuedRequest {
+                request: PosthogRequest::CaptureBatch { body },
+                response_tx: Some(tx),
+                ..Default::default()
+            });
+
+            let response = match rx.await {
+                Ok(response) => response,
+                Err(error) => Err(PosthogError::QueueError(error.into())),
+            };
+
+            if let Err(error) = response {
+                report.pending = event_count + batches.map(|batch| batch.len()).sum::<usize>();
+
+                return Err(PosthogError::ImportFailed {
+                    report,
+                    source: Box::new(error),
+                });
+            }
+
+            report.delivered += event_count;
+        }
+
+        Ok(report)
     }
 
+    /// Splits the events into batches that respect both the event count and the payload size limits.
+    ///
+    /// `envelope` is the body the events are sent in. Events that would exceed the size limit on
+    /// their own are left out, and their number is returned alongside the batches.
+    fn split_batch(
+        &self,
+        envelope: &Value,
+        events: Vec<Value>,
+        max_batch_size: usize,
+    ) -> (Vec<Vec<Value>>, usize) {
+        let mut empty_body = envelope.clone();
+        empty_body["batch"] = json!([]);
+        let envelope_bytes = empty_body.to_string().len();
+
+        let mut batches = vec![];
+        let mut batch = vec![];
+        let mut batch_bytes = envelope_bytes;
+        let mut oversized = 0;
+
+        for event in events {
+            // Every event but the first one is preceded by a comma.
+            let event_bytes = event.to_string().len() + 1;
+
+            if envelope_bytes + event_bytes > self.max_batch_bytes {
+                oversized += 1;
+                continue;
+            }
+
+            if !batch.is_empty()
+                && (batch.len() >= max_batch_size
+                    || batch_bytes + event_bytes > self.max_batch_bytes)
+            {
+                batches.push(std::mem::take(&mut batch));
+                batch_bytes = envelope_bytes;
+            }
+
+            batch_bytes += event_bytes;
+            batch.push(event);
+        }
+
+        if !batch.is_empty() {
+            batches.push(batch);
+        }
+
+        (batches, oversized)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "posthog_request",
+            skip_all,
+            fields(events = request.request.event_count())
+        )
+    )]
     async fn handle_request(client: QueueClient, request: QueuedRequest) {
+        let event_count = request.request.event_count();
+
+        let mut headers = HeaderMap::new();
+
         let (method, endpoint, body) = match request.request {
             PosthogRequest::CaptureEvent { body } => (Method::POST, "capture".to_string(), body),
 
@@ -170,6 +804,21 @@ impl QueueWorker {
                 Value::Null,
             ),
 
+            PosthogRequest::GetFeatureFlagDefinitions {
+                api_key,
+                personal_api_key,
+            } => {
+                if let Ok(value) = HeaderValue::from_str(&format!("Bearer {}", personal_api_key)) {
+                    headers.insert(AUTHORIZATION, value);
+                }
+
+                (
+                    Method::GET,
+                    format!("api/feature_flag/local_evaluation?token={}", api_key),
+                    Value::Null,
+                )
+            }
+
             PosthogRequest::Other {
                 method,
                 endpoint,
@@ -177,10 +826,53 @@ impl QueueWorker {
             } => (method, endpoint, json),
         };
 
-        let response = QueueWorker::send_request(client, method, endpoint, body).await;
+        #[cfg(feature = "tracing")]
+        let (started_at, logged_endpoint) = (std::time::Instant::now(), endpoint.clone());
+
+        let response = QueueWorker::send_request(client, method, endpoint, headers, body).await;
+
+        #[cfg(feature = "tracing")]
+        match &response {
+            Ok(_) => tracing::debug!(
+                endpoint = %logged_endpoint,
+                events = event_count,
+                latency_ms = started_at.elapsed().as_millis() as u64,
+                "request delivered"
+            ),
+            Err(error) => tracing::warn!(
+                endpoint = %logged_endpoint,
+                events = event_count,
+                latency_ms = started_at.elapsed().as_millis() as u64,
+                %error,
+                "request failed"
+            ),
+        }
+
+        // Batches that failed are left in the spool, and sent again by the next run.
+        let is_spooled = request.spool_segment.is_some();
+
+        if response.is_ok() {
+            if let Some(segment) = request.spool_segment {
+                spawn_blocking(move || segment.remove());
+            }
+        }
+
+        if let Some(tally) = request.tally {
+            let outcome = match response {
+                Ok(_) => &tally.delivered,
+                Err(_) if is_spooled => &tally.spooled,
+                Err(_) => &tally.dropped,
+            };
+
+            outcome.fetch_add(event_count, Ordering::SeqCst);
+            tally.pending.fetch_sub(event_count, Ordering::SeqCst);
+        }
 
         if let Some(response_tx) = request.response_tx {
-            response_tx.send(response).ok();
+            if response_tx.send(response).is_err() {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("response dropped, the caller stopped waiting for it");
+            }
         }
     }
 
@@ -188,24 +880,343 @@ impl QueueWorker {
         client: QueueClient,
         method: Method,
         endpoint: impl Into<String>,
+        mut headers: HeaderMap,
         json: Value,
     ) -> Result<Value, PosthogError> {
-        let response = client
-            .client
-            .request(method, &format!("{}/{}", client.base_url, endpoint.into()))
-            .header("Content-Type", "application/json")
-            .header("Accept", "application/json")
-            .json(&json)
-            .send()
-            .await
-            .map_err(PosthogError::HttpError)?;
-
-        if response.status().is_success() {
-            response.json().await.map_err(PosthogError::HttpError)
-        } else {
-            Err(PosthogError::HttpError(
-                response.error_for_status().unwrap_err(),
-            ))
+        if let Some(user_agent) = &client.user_agent {
+            headers.insert(USER_AGENT, user_agent.clone());
+        }
+
+        let endpoint = endpoint.into();
+        let request = TransportRequest {
+            method,
+            url: format!("{}/{}", client.base_url, endpoint),
+            endpoint,
+            headers,
+            body: json,
+        };
+
+        let retry_policy = &client.retry_policy;
+        let mut attempt = 1;
+
+        loop {
+            let response = client.transport.send(request.clone()).await;
+
+            #[cfg(feature = "tracing")]
+            match &response {
+                Ok(response) => tracing::debug!(
+                    endpoint = %request.endpoint,
+                    attempt,
+                    status = response.status.as_u16(),
+                    "received response"
+                ),
+                Err(error) => tracing::debug!(
+                    endpoint = %request.endpoint,
+                    attempt,
+                    %error,
+                    "request error"
+                ),
+            }
+
+            let (error, retry_after) = match response {
+                Ok(response) if response.status.is_success() => {
+                    return Ok(response.body);
+                }
+
+                Ok(response) => {
+                    let retry_after = retry_after(&response.headers);
+
+                    (
+                        PosthogError::from_response(response.status, response.body, retry_after),
+                        retry_after,
+                    )
+                }
+
+                Err(error) => (error, None),
+            };
+
+            if !retry_policy.should_retry(&error) || attempt >= retry_policy.max_attempts {
+                return Err(error);
+            }
+
+            let delay = retry_policy.delay(attempt, retry_after);
+
+            #[cfg(feature = "tracing")]
+            tracing::info!(
+                endpoint = %request.endpoint,
+                attempt,
+                delay_ms = delay.as_millis() as u64,
+                %error,
+                "retrying request"
+            );
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::{
+        client::{PosthogClient, TransportResponse},
+        data::{Event, Person},
+    };
+
+    /// Answers batches holding an event named `fail` with a 500, and everything else with a 200.
+    #[derive(Debug)]
+    struct StubTransport {
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl Transport for StubTransport {
+        async fn send(&self, request: TransportRequest) -> Result<TransportResponse, PosthogError> {
+            tokio::time::sleep(self.delay).await;
+
+            let is_failure = request.body["batch"]
+                .as_array()
+                .is_some_and(|batch| batch.iter().any(|event| event["event"] == "fail"));
+
+            Ok(TransportResponse {
+                status: if is_failure {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                } else {
+                    StatusCode::OK
+                },
+                headers: HeaderMap::new(),
+                body: Value::Null,
+            })
+        }
+    }
+
+    fn client(delay: Duration) -> PosthogClient {
+        PosthogClient::builder()
+            .base_url("http://localhost")
+            .api_key("test-api-key")
+            .transport(StubTransport { delay })
+            .retry_policy(RetryPolicy::disabled())
+            .build()
+            .unwrap()
+    }
+
+    fn enqueue(client: &PosthogClient, name: &str) -> Result<(), PosthogError> {
+        let person = Person::builder().distinct_id("user-1").build()?;
+        client.enqueue_event(&person, Event::builder().name(name).build()?)
+    }
+
+    #[tokio::test]
+    async fn flush_reports_the_batches_it_sent() {
+        let client = client(Duration::ZERO);
+
+        enqueue(&client, "first").unwrap();
+        enqueue(&client, "second").unwrap();
+        assert_eq!(
+            client.flush().await.unwrap(),
+            FlushReport {
+                delivered: 2,
+                ..Default::default()
+            }
+        );
+
+        enqueue(&client, "fail").unwrap();
+        assert_eq!(
+            client.flush().await.unwrap(),
+            FlushReport {
+                dropped: 1,
+                ..Default::default()
+            }
+        );
+
+        assert_eq!(client.flush().await.unwrap(), FlushReport::default());
+    }
+
+    #[tokio::test]
+    async fn shutdown_reports_delivered_events() {
+        let client = client(Duration::ZERO);
+
+        enqueue(&client, "first").unwrap();
+        assert_eq!(
+            client.shutdown(Duration::from_secs(5)).await.unwrap(),
+            FlushReport {
+                delivered: 1,
+                ..Default::default()
+            }
+        );
+
+        assert!(matches!(
+            enqueue(&client, "late"),
+            Err(PosthogError::QueueError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn shutdown_reports_events_in_flight_as_pending() {
+        let client = client(Duration::from_secs(60));
+
+        enqueue(&client, "first").unwrap();
+        enqueue(&client, "second").unwrap();
+        assert_eq!(
+            client.shutdown(Duration::from_millis(50)).await.unwrap(),
+            FlushReport {
+                pending: 2,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn shutdown_reports_queued_events_as_pending_if_not_dispatched() {
+        let directory = std::env::temp_dir().join(format!(
+            "hedgehog-queue-not-dispatched-{}",
+            std::process::id()
+        ));
+        let spool = Spool::open(&directory, u64::MAX).unwrap();
+
+        for index in 0..500 {
+            spool
+                .write(&Spool::encode(&[
+                    json!({ "event": "spooled", "index": index }),
+                ]))
+                .unwrap();
+        }
+
+        let client = PosthogClient::builder()
+            .base_url("http://localhost")
+            .api_key("test-api-key")
+            .transport(StubTransport {
+                delay: Duration::ZERO,
+            })
+            .spool_directory(&directory)
+            .build()
+            .unwrap();
+
+        // The worker is still reading the spooled batches when the timeout elapses.
+        enqueue(&client, "first").unwrap();
+        enqueue(&client, "second").unwrap();
+        assert_eq!(
+            client.shutdown(Duration::ZERO).await.unwrap(),
+            FlushReport {
+                pending: 2,
+                ..Default::default()
+            }
+        );
+
+        std::fs::remove_dir_all(directory).ok();
+    }
+
+    /// Runs a future to completion on the current thread, without a Tokio runtime.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct ThreadWaker(std::thread::Thread);
+
+        impl std::task::Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+        let mut context = std::task::Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+
+        loop {
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+
+            std::thread::park();
+        }
+    }
+
+    #[test]
+    fn shutdown_works_outside_of_tokio() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let client = PosthogClient::builder()
+            .base_url("http://localhost")
+            .api_key("test-api-key")
+            .transport(StubTransport {
+                delay: Duration::ZERO,
+            })
+            .runtime_handle(runtime.handle().clone())
+            .build()
+            .unwrap();
+
+        enqueue(&client, "first").unwrap();
+        assert_eq!(
+            block_on(client.shutdown(Duration::from_secs(5))).unwrap(),
+            FlushReport {
+                delivered: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    fn event(index: usize, padding: usize) -> Value {
+        json!({ "index": index, "padding": "x".repeat(padding) })
+    }
+
+    fn indexes(batches: &[Vec<Value>]) -> Vec<Vec<u64>> {
+        batches
+            .iter()
+            .map(|batch| {
+                batch
+                    .iter()
+                    .filter_map(|event| event["index"].as_u64())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn split_batch_limits_event_count() {
+        let queue = client(Duration::ZERO).queue;
+        let events = (0..5).map(|index| event(index, 0)).collect();
+
+        let (batches, oversized) = queue.split_batch(&json!({}), events, 2);
+
+        assert_eq!(indexes(&batches), vec![vec![0, 1], vec![2, 3], vec![4]]);
+        assert_eq!(oversized, 0);
+    }
+
+    #[tokio::test]
+    async fn split_batch_limits_size_and_drops_oversized_events() {
+        let envelope = json!({ "api_key": "test-api-key" });
+        let envelope_bytes = json!({ "api_key": "test-api-key", "batch": [] })
+            .to_string()
+            .len();
+        let event_bytes = event(0, 100).to_string().len() + 1;
+
+        // Room for exactly two events per batch.
+        let queue = PosthogClient::builder()
+            .base_url("http://localhost")
+            .api_key("test-api-key")
+            .max_batch_bytes(envelope_bytes + 2 * event_bytes)
+            .build()
+            .unwrap()
+            .queue;
+
+        let events = vec![
+            event(0, 100),
+            event(1, 100),
+            event(2, 1000),
+            event(3, 100),
+            event(4, 100),
+            event(5, 100),
+        ];
+
+        let (batches, oversized) = queue.split_batch(&envelope, events, 100);
+
+        assert_eq!(indexes(&batches), vec![vec![0, 1], vec![3, 4], vec![5]]);
+        assert_eq!(oversized, 1);
+
+        for batch in batches {
+            let mut body = envelope.clone();
+            body["batch"] = json!(batch);
+            assert!(body.to_string().len() <= envelope_bytes + 2 * event_bytes);
         }
     }
 }
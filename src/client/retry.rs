@@ -0,0 +1,196 @@
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use tokio::time::Duration;
+
+use crate::error::PosthogError;
+
+/// Controls how failed requests to the Posthog API are retried.
+///
+/// Delays grow exponentially from `base_delay` and are capped at `max_delay`.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub(crate) max_attempts: u32,
+    pub(crate) base_delay: Duration,
+    pub(crate) max_delay: Duration,
+    pub(crate) jitter: bool,
+    pub(crate) retry_on_server_error: bool,
+    pub(crate) retry_on_rate_limit: bool,
+    pub(crate) retry_on_timeout: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(10),
+            jitter: true,
+            retry_on_server_error: true,
+            retry_on_rate_limit: true,
+            retry_on_timeout: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A policy that sends every request exactly once.
+    pub fn disabled() -> Self {
+        Self::default().max_attempts(1)
+    }
+
+    /// The total number of attempts, including the first one.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Randomizes each delay between half and the full computed value.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Retry requests that failed with a 5xx status.
+    pub fn retry_on_server_error(mut self, retry_on_server_error: bool) -> Self {
+        self.retry_on_server_error = retry_on_server_error;
+        self
+    }
+
+    /// Retry requests that failed with a 429 status.
+    pub fn retry_on_rate_limit(mut self, retry_on_rate_limit: bool) -> Self {
+        self.retry_on_rate_limit = retry_on_rate_limit;
+        self
+    }
+
+    /// Retry requests that timed out or could not connect.
+    pub fn retry_on_timeout(mut self, retry_on_timeout: bool) -> Self {
+        self.retry_on_timeout = retry_on_timeout;
+        self
+    }
+
+    pub(crate) fn should_retry(&self, error: &PosthogError) -> bool {
+        if !error.is_retryable() {
+            return false;
+        }
+
+        match error {
+            PosthogError::ServerError { .. } => self.retry_on_server_error,
+            PosthogError::RateLimited { .. } => self.retry_on_rate_limit,
+            _ => self.retry_on_timeout,
+        }
+    }
+
+    /// The delay before the next attempt, `attempt` being the number of attempts made so far.
+    ///
+    /// A `Retry-After` value sent by the server takes precedence, but is still capped at `max_delay`.
+    pub(crate) fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_delay);
+        }
+
+        let exponent = attempt.saturating_sub(1).min(16);
+        let delay = self
+            .base_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay);
+
+        if self.jitter && !delay.is_zero() {
+            rand::thread_rng().gen_range(delay / 2..=delay)
+        } else {
+            delay
+        }
+    }
+}
+
+/// Parses the `Retry-After` header, which is either a number of seconds or an HTTP date.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?;
+
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::new()
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_secs(1))
+            .jitter(false)
+    }
+
+    #[test]
+    fn delay_grows_exponentially() {
+        let policy = policy();
+
+        assert_eq!(policy.delay(1, None), Duration::from_millis(100));
+        assert_eq!(policy.delay(2, None), Duration::from_millis(200));
+        assert_eq!(policy.delay(3, None), Duration::from_millis(400));
+        assert_eq!(policy.delay(4, None), Duration::from_millis(800));
+    }
+
+    #[test]
+    fn delay_is_capped() {
+        let policy = policy();
+
+        assert_eq!(policy.delay(5, None), Duration::from_secs(1));
+        assert_eq!(policy.delay(u32::MAX, None), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_stays_within_half_the_delay() {
+        let policy = policy().jitter(true);
+
+        for _ in 0..100 {
+            let delay = policy.delay(2, None);
+            assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(200));
+        }
+    }
+
+    #[test]
+    fn retry_after_takes_precedence() {
+        let policy = policy();
+
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_millis(700))),
+            Duration::from_millis(700)
+        );
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(30))),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn parses_retry_after_seconds() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("3"));
+
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(3)));
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+    }
+}
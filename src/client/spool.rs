@@ -0,0 +1,350 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use chrono::Utc;
+use serde_json::Value;
+
+const SEGMENT_EXTENSION: &str = "ndjson";
+const CORRUPT_EXTENSION: &str = "corrupt";
+
+/// Persists batches to disk until they have been delivered, so they survive a restart.
+///
+/// Every batch is written to its own newline-delimited JSON segment file, which is removed once
+/// the batch has been accepted by the Posthog API. Its methods do blocking file IO.
+#[derive(Debug)]
+pub(crate) struct Spool {
+    directory: PathBuf,
+    max_bytes: u64,
+    next_segment: AtomicU64,
+    /// The total size of the segments, shared with the segments so they can release their share.
+    size: Arc<AtomicU64>,
+}
+
+/// A segment file holding a single batch of events.
+#[derive(Debug)]
+pub(crate) struct SpoolSegment {
+    path: PathBuf,
+    bytes: u64,
+    spool_size: Arc<AtomicU64>,
+    is_corrupt: bool,
+}
+
+impl Spool {
+    pub(crate) fn open(directory: impl Into<PathBuf>, max_bytes: u64) -> io::Result<Self> {
+        let directory = directory.into();
+        fs::create_dir_all(&*directory)?;
+
+        let mut spool = Self {
+            directory,
+            max_bytes,
+            next_segment: AtomicU64::new(0),
+            size: Arc::default(),
+        };
+
+        // The directory is only scanned once, the size is then kept up to date as segments are
+        // written and removed.
+        let segments = spool.segments()?;
+        let size = segments.iter().map(|segment| segment.bytes).sum();
+        spool.size = Arc::new(AtomicU64::new(size));
+
+        // Continue the sequence of the previous run, in case it wrote within the same millisecond.
+        let next_segment = segments
+            .iter()
+            .filter_map(SpoolSegment::sequence)
+            .max()
+            .map_or(0, |sequence| sequence + 1);
+        spool.next_segment = AtomicU64::new(next_segment);
+
+        Ok(spool)
+    }
+
+    /// Serializes events into the contents of a segment.
+    pub(crate) fn encode(events: &[Value]) -> Vec<u8> {
+        let mut contents = vec![];
+        for event in events {
+            contents.extend_from_slice(event.to_string().as_bytes());
+            contents.push(b'\n');
+        }
+
+        contents
+    }
+
+    /// Writes events encoded with [`Spool::encode`] to a new segment.
+    ///
+    /// Returns `None` without writing anything if the spool would grow past its size limit.
+    pub(crate) fn write(&self, contents: &[u8]) -> io::Result<Option<SpoolSegment>> {
+        let bytes = contents.len() as u64;
+
+        // Reserve the space up front, so concurrent writes can't exceed the limit together.
+        let previous_size = self.size.fetch_add(bytes, Ordering::SeqCst);
+        if previous_size + bytes > self.max_bytes {
+            self.size.fetch_sub(bytes, Ordering::SeqCst);
+            return Ok(None);
+        }
+
+        let segment = SpoolSegment {
+            path: self.segment_path(),
+            bytes,
+            spool_size: self.size.clone(),
+            is_corrupt: false,
+        };
+
+        if let Err(error) = segment.create(contents) {
+            self.size.fetch_sub(bytes, Ordering::SeqCst);
+            return Err(error);
+        }
+
+        Ok(Some(segment))
+    }
+
+    fn segment_path(&self) -> PathBuf {
+        // Segment names sort in the order they were written, including across restarts.
+        let sequence = self.next_segment.fetch_add(1, Ordering::SeqCst);
+        let name = format!(
+            "{:020}-{:010}.{}",
+            Utc::now().timestamp_millis(),
+            sequence,
+            SEGMENT_EXTENSION
+        );
+        self.directory.join(name)
+    }
+
+    /// Lists the segments left over by previous runs, oldest first.
+    pub(crate) fn segments(&self) -> io::Result<Vec<SpoolSegment>> {
+        let mut segments = fs::read_dir(&self.directory)?
+            .filter_map(Result::ok)
+            .filter(|entry| is_segment(&entry.path()))
+            .filter_map(|entry| {
+                Some(SpoolSegment {
+                    bytes: entry.metadata().ok()?.len(),
+                    path: entry.path(),
+                    spool_size: self.size.clone(),
+                    is_corrupt: false,
+                })
+            })
+            .collect::<Vec<_>>();
+        segments.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(segments)
+    }
+}
+
+impl SpoolSegment {
+    fn create(&self, contents: &[u8]) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&self.path)?;
+
+        // Don't leave a partial segment behind to be replayed.
+        file.write_all(contents)
+            .and_then(|()| file.sync_data())
+            .inspect_err(|_| {
+                fs::remove_file(&self.path).ok();
+            })
+    }
+
+    /// The position of the segment among the ones written in the same millisecond.
+    fn sequence(&self) -> Option<u64> {
+        let name = self.path.file_stem()?.to_str()?;
+        name.split_once('-')?.1.parse().ok()
+    }
+
+    /// Reads the events stored in the segment.
+    ///
+    /// Lines that cannot be parsed, such as one cut short by a crash, are skipped. The segment is
+    /// then marked as corrupt, so it is kept for inspection instead of being removed.
+    pub(crate) fn read(&mut self) -> Vec<Value> {
+        let Ok(contents) = fs::read(&self.path) else {
+            self.is_corrupt = true;
+            return vec![];
+        };
+
+        let mut events = vec![];
+
+        for line in contents.split(|byte| *byte == b'\n') {
+            if line.is_empty() {
+                continue;
+            }
+
+            match serde_json::from_slice::<Value>(line) {
+                Ok(event) if event.is_object() => events.push(event),
+                _ => self.is_corrupt = true,
+            }
+        }
+
+        events
+    }
+
+    /// Removes the segment, or renames it with the `corrupt` extension if some of its lines could
+    /// not be read.
+    pub(crate) fn remove(self) {
+        let result = if self.is_corrupt {
+            fs::rename(&self.path, self.path.with_extension(CORRUPT_EXTENSION))
+        } else {
+            fs::remove_file(&self.path)
+        };
+
+        if result.is_ok() {
+            self.spool_size.fetch_sub(self.bytes, Ordering::SeqCst);
+
+            #[cfg(feature = "tracing")]
+            if self.is_corrupt {
+                tracing::warn!(path = %self.path.display(), "kept a corrupt spool segment");
+            }
+        }
+    }
+}
+
+fn is_segment(path: &Path) -> bool {
+    path.is_file() && path.extension().is_some_and(|ext| ext == SEGMENT_EXTENSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// An empty directory that is unique to the test, and removed once the test ends.
+    struct TestDirectory(PathBuf);
+
+    impl std::ops::Deref for TestDirectory {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TestDirectory {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    fn directory(name: &str) -> TestDirectory {
+        let directory =
+            std::env::temp_dir().join(format!("hedgehog-spool-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&directory).ok();
+
+        TestDirectory(directory)
+    }
+
+    fn files_with_extension(directory: &Path, extension: &str) -> usize {
+        fs::read_dir(directory)
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == extension))
+            .count()
+    }
+
+    #[test]
+    fn round_trip() {
+        let directory = directory("round-trip");
+        let spool = Spool::open(&*directory, u64::MAX).unwrap();
+        let events = vec![json!({ "event": "first" }), json!({ "event": "second" })];
+
+        spool.write(&Spool::encode(&events)).unwrap().unwrap();
+
+        let mut segments = spool.segments().unwrap();
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].read(), events);
+        assert!(!segments[0].is_corrupt);
+    }
+
+    #[test]
+    fn respects_max_bytes_and_tracks_size() {
+        let directory = directory("max-bytes");
+        let contents = Spool::encode(&[json!({ "event": "first" })]);
+        let bytes = contents.len() as u64;
+
+        let spool = Spool::open(&*directory, bytes * 2).unwrap();
+        let first = spool.write(&contents).unwrap().unwrap();
+        let second = spool.write(&contents).unwrap().unwrap();
+        assert!(spool.write(&contents).unwrap().is_none());
+        assert_eq!(spool.size.load(Ordering::SeqCst), bytes * 2);
+
+        // The size is read back from the directory when the spool is opened again.
+        assert_eq!(
+            Spool::open(&*directory, bytes * 2)
+                .unwrap()
+                .size
+                .load(Ordering::SeqCst),
+            bytes * 2
+        );
+
+        first.remove();
+        assert_eq!(spool.size.load(Ordering::SeqCst), bytes);
+        assert!(spool.write(&contents).unwrap().is_some());
+
+        second.remove();
+        assert_eq!(spool.size.load(Ordering::SeqCst), bytes);
+        assert_eq!(files_with_extension(&directory, SEGMENT_EXTENSION), 1);
+    }
+
+    #[test]
+    fn skips_corrupt_lines_and_keeps_the_segment() {
+        let directory = directory("corrupt");
+        fs::create_dir_all(&*directory).unwrap();
+
+        let mut contents = b"{\"event\":\"first\"}\n".to_vec();
+        contents.extend_from_slice(b"\xff\xfe\n");
+        contents.extend_from_slice(b"[1, 2]\n");
+        contents.extend_from_slice(b"{\"event\":\"second\"}\n");
+        contents.extend_from_slice(b"{\"event\":\"thi");
+        fs::write(directory.join("0.ndjson"), &contents).unwrap();
+
+        let spool = Spool::open(&*directory, u64::MAX).unwrap();
+        assert_eq!(spool.size.load(Ordering::SeqCst), contents.len() as u64);
+
+        let mut segment = spool.segments().unwrap().pop().unwrap();
+        assert_eq!(
+            segment.read(),
+            vec![json!({ "event": "first" }), json!({ "event": "second" })]
+        );
+        assert!(segment.is_corrupt);
+
+        segment.remove();
+        assert_eq!(spool.size.load(Ordering::SeqCst), 0);
+        assert_eq!(files_with_extension(&directory, SEGMENT_EXTENSION), 0);
+        assert_eq!(files_with_extension(&directory, CORRUPT_EXTENSION), 1);
+        assert!(spool.segments().unwrap().is_empty());
+    }
+
+    #[test]
+    fn lists_segments_in_write_order() {
+        let directory = directory("order");
+        let spool = Spool::open(&*directory, u64::MAX).unwrap();
+
+        for index in 0..12 {
+            spool
+                .write(&Spool::encode(&[json!({ "index": index })]))
+                .unwrap()
+                .unwrap();
+        }
+
+        // A new run continues after the segments of the previous one.
+        let spool = Spool::open(&*directory, u64::MAX).unwrap();
+        spool
+            .write(&Spool::encode(&[json!({ "index": 12 })]))
+            .unwrap()
+            .unwrap();
+
+        let indexes = spool
+            .segments()
+            .unwrap()
+            .iter_mut()
+            .flat_map(|segment| segment.read())
+            .map(|event| event["index"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(indexes, (0..13).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,62 @@
+use serde_json::Value;
+
+use super::PosthogClient;
+
+impl PosthogClient {
+    /// Registers a property that is attached to every event captured by this client and its clones.
+    ///
+    /// Properties set on an event take precedence over super properties with the same key.
+    pub fn register(&self, key: impl Into<String>, value: impl Into<Value>) {
+        self.super_properties
+            .write()
+            .unwrap()
+            .insert(key.into(), value.into());
+    }
+
+    /// Removes a property registered with [`PosthogClient::register`].
+    pub fn unregister(&self, key: &str) {
+        self.super_properties.write().unwrap().remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        data::{Event, Person},
+        testing::Recorder,
+    };
+
+    use super::PosthogClient;
+
+    #[tokio::test]
+    async fn super_properties_are_attached_to_events() {
+        let recorder = Recorder::new();
+        let client = PosthogClient::builder()
+            .base_url("http://localhost")
+            .api_key("test-api-key")
+            .recorder(recorder.clone())
+            .super_property("app", "web")
+            .build()
+            .unwrap();
+        let person = Person::builder().distinct_id("user-1").build().unwrap();
+
+        client.register("region", "eu");
+        client.register("plan", "free");
+        client.unregister("region");
+
+        Event::builder()
+            .name("signed up")
+            .property("plan", "pro")
+            .build()
+            .unwrap()
+            .enqueue(&person, &client)
+            .unwrap();
+
+        let event = recorder.assert_captured("signed up");
+        assert_eq!(event.property("app"), Some(&json!("web")));
+        assert_eq!(event.property("plan"), Some(&json!("pro")));
+        assert_eq!(event.property("region"), None);
+    }
+}
@@ -0,0 +1,91 @@
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+use reqwest::{
+    header::{HeaderMap, ACCEPT, CONTENT_TYPE},
+    Client, Method, StatusCode,
+};
+use serde_json::Value;
+
+use crate::error::PosthogError;
+
+/// A request to the Posthog API, as handed to a [`Transport`].
+#[derive(Debug, Clone)]
+pub struct TransportRequest {
+    pub method: Method,
+    /// The full URL, including the base URL the client was built with.
+    pub url: String,
+    /// The endpoint relative to the base URL, such as `batch` or `decide?v=3`.
+    pub endpoint: String,
+    pub headers: HeaderMap,
+    pub body: Value,
+}
+
+/// The response returned by a [`Transport`].
+#[derive(Debug, Clone)]
+pub struct TransportResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    /// The decoded JSON body. Bodies that are not valid JSON are returned as a string,
+    /// and empty bodies as `null`.
+    pub body: Value,
+}
+
+/// Sends requests to the Posthog API.
+///
+/// The client uses [`ReqwestTransport`] by default. Implement this trait to route requests through
+/// a different HTTP stack or a test double. Non-2xx responses should be returned as a
+/// [`TransportResponse`] rather than an error, so the client can decide whether to retry them.
+#[async_trait]
+pub trait Transport: Debug + Send + Sync {
+    async fn send(&self, request: TransportRequest) -> Result<TransportResponse, PosthogError>;
+}
+
+/// The default [`Transport`], backed by a [`reqwest::Client`].
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    client: Client,
+}
+
+impl ReqwestTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses a pre-built client, for example one configured with proxies, TLS roots or timeouts.
+    pub fn with_client(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl Transport for ReqwestTransport {
+    async fn send(&self, request: TransportRequest) -> Result<TransportResponse, PosthogError> {
+        let response = self
+            .client
+            .request(request.method, &request.url)
+            .headers(request.headers)
+            .header(CONTENT_TYPE, "application/json")
+            .header(ACCEPT, "application/json")
+            .json(&request.body)
+            .send()
+            .await?;
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = response.bytes().await?;
+
+        let body = if bytes.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&bytes)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()))
+        };
+
+        Ok(TransportResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+}
@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 
-use serde_json::Value;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use uuid::Uuid;
 
 use crate::{client::PosthogClient, error::PosthogError};
 
@@ -10,6 +12,10 @@ pub struct Event {
     pub(crate) name: String,
     pub(crate) properties: Option<HashMap<String, Value>>,
     pub(crate) is_identify: bool,
+    pub(crate) groups: HashMap<String, String>,
+    pub(crate) timestamp: Option<DateTime<Utc>>,
+    pub(crate) uuid: Option<Uuid>,
+    pub(crate) process_person_profile: Option<bool>,
 }
 
 impl Event {
@@ -18,6 +24,10 @@ impl Event {
             name: None,
             properties: HashMap::new(),
             is_identify: false,
+            groups: HashMap::new(),
+            timestamp: None,
+            uuid: None,
+            process_person_profile: None,
         }
     }
 
@@ -41,10 +51,28 @@ impl Event {
                 .include_person_properties(self.is_identify)
                 .use_set_syntax(self.is_identify)
                 .include_ip(true)
-                .include_feature_flags(true),
+                .include_feature_flags(true)
+                .include_groups(true),
         );
         properties.extend(person_event_properties);
 
+        if self.name == "$identify" {
+            if let Some(anon_distinct_id) = &person.anon_distinct_id {
+                properties.insert(
+                    "$anon_distinct_id".to_string(),
+                    Value::String(anon_distinct_id.clone()),
+                );
+            }
+        }
+
+        // Groups set on the event take precedence over the person's groups of the same type.
+        if !self.groups.is_empty() {
+            let mut groups = person.group_keys();
+            groups.extend(self.groups.clone());
+
+            properties.insert("$groups".to_string(), json!(groups));
+        }
+
         properties
     }
 }
@@ -53,6 +81,10 @@ pub struct EventBuilder {
     name: Option<String>,
     properties: HashMap<String, Value>,
     is_identify: bool,
+    groups: HashMap<String, String>,
+    timestamp: Option<DateTime<Utc>>,
+    uuid: Option<Uuid>,
+    process_person_profile: Option<bool>,
 }
 
 impl EventBuilder {
@@ -76,6 +108,33 @@ impl EventBuilder {
         self
     }
 
+    /// Associates the event with a group, in addition to the groups of the person.
+    pub fn group(mut self, group_type: impl Into<String>, group_key: impl Into<String>) -> Self {
+        self.groups.insert(group_type.into(), group_key.into());
+        self
+    }
+
+    /// Sets when the event happened, for example when backfilling historical data.
+    /// Defaults to the time the event is enqueued.
+    pub fn timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Sets the event UUID, which Posthog uses to deduplicate events that are sent more than once.
+    /// Defaults to a random UUID.
+    pub fn uuid(mut self, uuid: Uuid) -> Self {
+        self.uuid = Some(uuid);
+        self
+    }
+
+    /// Sets whether Posthog creates or updates a person profile for this event. Personless events
+    /// are cheaper to process. Defaults to the client setting.
+    pub fn process_person_profile(mut self, process_person_profile: bool) -> Self {
+        self.process_person_profile = Some(process_person_profile);
+        self
+    }
+
     pub fn identify(mut self) -> Self {
         self.is_identify = true;
         self
@@ -92,6 +151,10 @@ impl EventBuilder {
                 Some(self.properties)
             },
             is_identify: self.is_identify,
+            groups: self.groups,
+            timestamp: self.timestamp,
+            uuid: self.uuid,
+            process_person_profile: self.process_person_profile,
         })
     }
 }
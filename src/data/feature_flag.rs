@@ -1,7 +1,9 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::Path};
 
 use serde_json::Value;
 
+use crate::error::PosthogError;
+
 #[derive(Debug, Clone)]
 pub struct FeatureFlagCollection {
     pub(crate) flags: HashMap<String, FeatureFlag>,
@@ -12,6 +14,56 @@ impl FeatureFlagCollection {
         Self { flags }
     }
 
+    /// Builds a collection from flag keys and their variants, such as `true` or `"control"`.
+    pub fn from_map<K, V>(flags: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<Value>,
+    {
+        Self::new(
+            flags
+                .into_iter()
+                .map(|(key, variant)| (key.into(), FeatureFlag::new(variant)))
+                .collect(),
+        )
+    }
+
+    /// Builds a collection from either a `/decide` response, with `featureFlags` and
+    /// `featureFlagPayloads`, or an object of flag keys and their variants.
+    pub fn from_json(json: Value) -> Result<Self, PosthogError> {
+        let mut json = serde_json::from_value::<HashMap<String, Value>>(json)?;
+
+        let Some(feature_flags) = json.remove("featureFlags") else {
+            return Ok(Self::from_map(json));
+        };
+
+        let mut collection = Self::from_map(serde_json::from_value::<HashMap<String, Value>>(
+            feature_flags,
+        )?);
+
+        if let Some(payloads) = json.remove("featureFlagPayloads") {
+            for (key, payload) in serde_json::from_value::<HashMap<String, Value>>(payloads)? {
+                if let Some(flag) = collection.flags.get_mut(&key) {
+                    flag.payload = Some(parse_payload(payload));
+                }
+            }
+        }
+
+        Ok(collection)
+    }
+
+    /// Reads a collection from a JSON file, in any format accepted by
+    /// [`FeatureFlagCollection::from_json`].
+    pub fn from_json_file(path: impl AsRef<Path>) -> Result<Self, PosthogError> {
+        let json = std::fs::read(path).map_err(PosthogError::FeatureFlagFileError)?;
+        Self::from_json(serde_json::from_slice(&json)?)
+    }
+
+    /// Adds a flag, replacing any flag with the same key.
+    pub fn insert(&mut self, key: impl Into<String>, flag: FeatureFlag) {
+        self.flags.insert(key.into(), flag);
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = (&str, &FeatureFlag)> {
         self.flags.iter().map(|(k, v)| (k.as_str(), v))
     }
@@ -60,6 +112,19 @@ pub struct FeatureFlag {
 }
 
 impl FeatureFlag {
+    pub fn new(variant: impl Into<Value>) -> Self {
+        Self {
+            variant: variant.into().into(),
+            payload: None,
+        }
+    }
+
+    /// Sets the payload. JSON encoded strings are decoded, like payloads returned by Posthog.
+    pub fn with_payload(mut self, payload: impl Into<Value>) -> Self {
+        self.payload = Some(parse_payload(payload.into()));
+        self
+    }
+
     pub fn variant(&self) -> &FeatureFlagData {
         &self.variant
     }
@@ -82,6 +147,11 @@ impl FeatureFlag {
         }
     }
 
+    /// Whether the flag is enabled, which is the case for every variant except `false`.
+    pub fn is_enabled(&self) -> bool {
+        !matches!(self.variant, FeatureFlagData::Boolean(false))
+    }
+
     pub fn payload(&self) -> Option<&FeatureFlagData> {
         self.payload.as_ref()
     }
@@ -139,3 +209,14 @@ impl From<Value> for FeatureFlagData {
         }
     }
 }
+
+/// Payloads are usually stored as JSON encoded strings, which are decoded. Other values, and
+/// strings that are not valid JSON, are used as they are.
+pub(crate) fn parse_payload(payload: Value) -> FeatureFlagData {
+    match payload {
+        Value::String(s) => serde_json::from_str::<Value>(&s)
+            .unwrap_or(Value::String(s))
+            .into(),
+        other => other.into(),
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::error::PosthogError;
+
+/// A group of people, such as a company, team or project.
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub(crate) group_type: String,
+    pub(crate) group_key: String,
+    pub(crate) properties: Option<HashMap<String, Value>>,
+}
+
+impl Group {
+    pub fn builder() -> GroupBuilder {
+        GroupBuilder {
+            group_type: None,
+            group_key: None,
+            properties: HashMap::new(),
+        }
+    }
+
+    pub fn group_type(&self) -> &str {
+        &self.group_type
+    }
+
+    pub fn group_key(&self) -> &str {
+        &self.group_key
+    }
+
+    pub fn properties(&self) -> Option<&HashMap<String, Value>> {
+        self.properties.as_ref()
+    }
+}
+
+pub struct GroupBuilder {
+    group_type: Option<String>,
+    group_key: Option<String>,
+    properties: HashMap<String, Value>,
+}
+
+impl GroupBuilder {
+    pub fn group_type(mut self, group_type: impl Into<String>) -> Self {
+        self.group_type = Some(group_type.into());
+        self
+    }
+
+    pub fn group_key(mut self, group_key: impl Into<String>) -> Self {
+        self.group_key = Some(group_key.into());
+        self
+    }
+
+    pub fn property(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.properties.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn properties(mut self, properties: HashMap<String, Value>) -> Self {
+        self.properties = properties;
+        self
+    }
+
+    pub fn build(self) -> Result<Group, PosthogError> {
+        let group_type = self.group_type.ok_or(PosthogError::GroupTypeRequired)?;
+        let group_key = self.group_key.ok_or(PosthogError::GroupKeyRequired)?;
+
+        Ok(Group {
+            group_type,
+            group_key,
+            properties: if self.properties.is_empty() {
+                None
+            } else {
+                Some(self.properties)
+            },
+        })
+    }
+}
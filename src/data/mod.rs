@@ -1,9 +1,11 @@
 mod early_access;
 mod event;
 mod feature_flag;
+mod group;
 mod person;
 
 pub use early_access::*;
 pub use event::*;
 pub use feature_flag::*;
+pub use group::*;
 pub use person::*;
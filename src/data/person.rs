@@ -4,7 +4,7 @@ use serde_json::{json, Value};
 
 use crate::error::PosthogError;
 
-use super::FeatureFlagCollection;
+use super::{FeatureFlagCollection, Group};
 
 #[derive(Default, Debug, Clone)]
 pub struct PropertyFilter {
@@ -12,6 +12,7 @@ pub struct PropertyFilter {
     pub(crate) use_set_syntax: bool,
     pub(crate) include_ip: bool,
     pub(crate) include_feature_flags: bool,
+    pub(crate) include_groups: bool,
 }
 
 impl PropertyFilter {
@@ -38,14 +39,23 @@ impl PropertyFilter {
         self.include_feature_flags = include_feature_flags;
         self
     }
+
+    pub fn include_groups(mut self, include_groups: bool) -> Self {
+        self.include_groups = include_groups;
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Person {
     pub(crate) distinct_id: String,
     pub(crate) properties: Option<HashMap<String, Value>>,
+    pub(crate) once_properties: Option<HashMap<String, Value>>,
+    pub(crate) unset_properties: Vec<String>,
     pub(crate) stored_feature_flags: Option<FeatureFlagCollection>,
     pub(crate) client_ip: Option<String>,
+    pub(crate) groups: HashMap<String, Group>,
+    pub(crate) anon_distinct_id: Option<String>,
 }
 
 impl Person {
@@ -53,7 +63,11 @@ impl Person {
         PersonBuilder {
             distinct_id: None,
             properties: HashMap::new(),
+            once_properties: HashMap::new(),
             client_ip: None,
+            groups: HashMap::new(),
+            anon_distinct_id: None,
+            feature_flags: None,
         }
     }
 
@@ -65,18 +79,105 @@ impl Person {
         self.client_ip = Some(client_ip.into());
     }
 
+    /// Removes a property from the person. The removal is sent with every `$identify` event until
+    /// [`Person::clear_unset_properties`] is called.
+    pub fn unset_property(&mut self, key: impl Into<String>) {
+        let key = key.into();
+
+        for properties in [&mut self.properties, &mut self.once_properties] {
+            if let Some(map) = properties {
+                map.remove(&key);
+
+                if map.is_empty() {
+                    *properties = None;
+                }
+            }
+        }
+
+        if !self.unset_properties.contains(&key) {
+            self.unset_properties.push(key);
+        }
+    }
+
+    /// Stops sending the properties removed with [`Person::unset_property`], for example once they
+    /// have been sent with an `$identify` event.
+    pub fn clear_unset_properties(&mut self) {
+        self.unset_properties.clear();
+    }
+
+    pub fn anon_distinct_id(&self) -> Option<&str> {
+        self.anon_distinct_id.as_deref()
+    }
+
+    /// Sets the anonymous distinct ID the person used before being identified. It is sent with
+    /// `$identify` events, so the anonymous session is merged into the identified person.
+    pub fn set_anon_distinct_id(&mut self, anon_distinct_id: impl Into<String>) {
+        self.anon_distinct_id = Some(anon_distinct_id.into());
+    }
+
     pub fn stored_feature_flags(&self) -> Option<&FeatureFlagCollection> {
         self.stored_feature_flags.as_ref()
     }
 
+    /// Stores feature flags in the person without evaluating them, for example ones bootstrapped
+    /// from a file. They are used by flag lookups and included in events like evaluated flags.
+    pub fn set_feature_flags(&mut self, feature_flags: FeatureFlagCollection) {
+        self.stored_feature_flags = Some(feature_flags);
+    }
+
+    /// Associates the person with a group, replacing any group of the same type.
+    pub fn set_group(&mut self, group: Group) {
+        self.groups.insert(group.group_type.clone(), group);
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = &Group> {
+        self.groups.values()
+    }
+
+    /// The key of each group the person belongs to, by group type.
+    pub(crate) fn group_keys(&self) -> HashMap<String, String> {
+        self.groups
+            .values()
+            .map(|group| (group.group_type.clone(), group.group_key.clone()))
+            .collect()
+    }
+
+    /// The properties of each group the person belongs to, by group type.
+    pub(crate) fn group_properties(&self) -> HashMap<String, HashMap<String, Value>> {
+        self.groups
+            .values()
+            .map(|group| {
+                (
+                    group.group_type.clone(),
+                    group.properties.clone().unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+
     pub(crate) fn build_properties(&self, filter: PropertyFilter) -> HashMap<String, Value> {
         let mut properties = HashMap::new();
 
         if filter.include_person_properties {
-            if let Some(person_properties) = &self.properties {
-                if filter.use_set_syntax {
+            if filter.use_set_syntax {
+                if let Some(person_properties) = &self.properties {
                     properties.insert("$set".to_string(), json!(person_properties));
-                } else {
+                }
+
+                if let Some(once_properties) = &self.once_properties {
+                    properties.insert("$set_once".to_string(), json!(once_properties));
+                }
+
+                if !self.unset_properties.is_empty() {
+                    properties.insert("$unset".to_string(), json!(self.unset_properties));
+                }
+            } else {
+                // Properties set once never overwrite regular ones.
+                if let Some(once_properties) = &self.once_properties {
+                    properties.extend(once_properties.clone());
+                }
+
+                if let Some(person_properties) = &self.properties {
                     properties.extend(person_properties.clone());
                 }
             }
@@ -96,6 +197,10 @@ impl Person {
             }
         }
 
+        if filter.include_groups && !self.groups.is_empty() {
+            properties.insert("$groups".to_string(), json!(self.group_keys()));
+        }
+
         properties
     }
 }
@@ -103,7 +208,11 @@ impl Person {
 pub struct PersonBuilder {
     distinct_id: Option<String>,
     properties: HashMap<String, Value>,
+    once_properties: HashMap<String, Value>,
     client_ip: Option<String>,
+    groups: HashMap<String, Group>,
+    anon_distinct_id: Option<String>,
+    feature_flags: Option<FeatureFlagCollection>,
 }
 
 impl PersonBuilder {
@@ -122,11 +231,34 @@ impl PersonBuilder {
         self
     }
 
+    /// Sets a property only if the person does not have it yet, such as a signup source.
+    pub fn property_once(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.once_properties.insert(key.into(), value.into());
+        self
+    }
+
     pub fn client_ip(mut self, client_ip: impl Into<String>) -> Self {
         self.client_ip = Some(client_ip.into());
         self
     }
 
+    /// Sets the anonymous distinct ID the person used before being identified.
+    pub fn anon_distinct_id(mut self, anon_distinct_id: impl Into<String>) -> Self {
+        self.anon_distinct_id = Some(anon_distinct_id.into());
+        self
+    }
+
+    pub fn group(mut self, group: Group) -> Self {
+        self.groups.insert(group.group_type.clone(), group);
+        self
+    }
+
+    /// Bootstraps the person's feature flags. See [`Person::set_feature_flags`].
+    pub fn feature_flags(mut self, feature_flags: FeatureFlagCollection) -> Self {
+        self.feature_flags = Some(feature_flags);
+        self
+    }
+
     pub fn build(self) -> Result<Person, PosthogError> {
         let distinct_id = self.distinct_id.ok_or(PosthogError::DistinctIdRequired)?;
 
@@ -137,8 +269,16 @@ impl PersonBuilder {
             } else {
                 Some(self.properties)
             },
-            stored_feature_flags: None,
+            once_properties: if self.once_properties.is_empty() {
+                None
+            } else {
+                Some(self.once_properties)
+            },
+            unset_properties: vec![],
+            stored_feature_flags: self.feature_flags,
             client_ip: self.client_ip,
+            groups: self.groups,
+            anon_distinct_id: self.anon_distinct_id,
         })
     }
 }
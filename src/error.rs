@@ -1,3 +1,13 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::Value;
+
+use crate::client::FlushReport;
+
+/// The underlying cause of a [`PosthogError::FeatureFlagError`] or [`PosthogError::QueueError`].
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
 #[derive(thiserror::Error, Debug)]
 pub enum PosthogError {
     #[error("Base URL is required")]
@@ -9,15 +19,98 @@ pub enum PosthogError {
     DistinctIdRequired,
     #[error("Event name is required")]
     EventNameRequired,
+    #[error("Group type is required")]
+    GroupTypeRequired,
+    #[error("Group key is required")]
+    GroupKeyRequired,
 
     #[error("HTTP error: {0}")]
-    HttpError(#[from] reqwest::Error),
+    HttpError(#[source] reqwest::Error),
+    #[error("Request timed out")]
+    Timeout,
+    #[error("Unauthorized ({status}): {body}")]
+    Unauthorized { status: StatusCode, body: String },
+    #[error("Rate limited")]
+    RateLimited { retry_after: Option<Duration> },
+    #[error("Payload too large")]
+    PayloadTooLarge,
+    #[error("Server error ({status}): {body}")]
+    ServerError { status: StatusCode, body: String },
+    #[error("Unexpected HTTP status ({status}): {body}")]
+    StatusError { status: StatusCode, body: String },
     #[error("JSON error: {0}")]
     JsonError(#[from] serde_json::Error),
 
-    #[error("Server failed to compute feature flags")]
-    FeatureFlagError,
+    #[error("Failed to evaluate feature flags")]
+    FeatureFlagError(#[source] BoxError),
+    #[error("Failed to read feature flags file")]
+    FeatureFlagFileError(#[source] std::io::Error),
 
     #[error("Failed to enqueue request")]
-    QueueError,
+    QueueError(#[source] BoxError),
+    #[error("Event queue is full")]
+    QueueFull,
+    /// An import stopped at a batch that could not be delivered. The report counts the events
+    /// delivered before it, and the events that were not sent as pending.
+    #[error("Import stopped after {} delivered events", report.delivered)]
+    ImportFailed {
+        report: FlushReport,
+        #[source]
+        source: Box<PosthogError>,
+    },
+
+    #[error("Spool error: {0}")]
+    SpoolError(#[from] std::io::Error),
+
+    #[error("No Tokio runtime is available, build the client within one or pass a runtime handle")]
+    NoRuntime,
+    #[error("Failed to start the runtime")]
+    RuntimeError(#[source] std::io::Error),
+}
+
+impl PosthogError {
+    /// Whether sending the same request again could succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            PosthogError::HttpError(error) => error.is_connect(),
+            PosthogError::Timeout
+            | PosthogError::RateLimited { .. }
+            | PosthogError::ServerError { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Builds the error matching a non-2xx response.
+    pub(crate) fn from_response(
+        status: StatusCode,
+        body: Value,
+        retry_after: Option<Duration>,
+    ) -> Self {
+        let body = match body {
+            Value::String(body) => body,
+            Value::Null => String::new(),
+            body => body.to_string(),
+        };
+
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                PosthogError::Unauthorized { status, body }
+            }
+            StatusCode::TOO_MANY_REQUESTS => PosthogError::RateLimited { retry_after },
+            StatusCode::PAYLOAD_TOO_LARGE => PosthogError::PayloadTooLarge,
+            StatusCode::REQUEST_TIMEOUT => PosthogError::Timeout,
+            status if status.is_server_error() => PosthogError::ServerError { status, body },
+            status => PosthogError::StatusError { status, body },
+        }
+    }
+}
+
+impl From<reqwest::Error> for PosthogError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            PosthogError::Timeout
+        } else {
+            PosthogError::HttpError(error)
+        }
+    }
 }
@@ -0,0 +1,285 @@
+//!
+//! A [`tracing_subscriber::Layer`] that forwards `tracing` events to Posthog.
+//!
+//! # Example
+//! ```no_run
+//! use hedgehog_rs::client::PosthogClient;
+//! use hedgehog_rs::layer::PosthogLayer;
+//! use tracing_subscriber::prelude::*;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let client = PosthogClient::builder()
+//!         .base_url("https://app.posthog.com")
+//!         .api_key("your-api-key")
+//!         .build()?;
+//!
+//!     tracing_subscriber::registry()
+//!         .with(PosthogLayer::builder(client).build())
+//!         .init();
+//!
+//!     let span = tracing::info_span!("request", distinct_id = "12345");
+//!     let _guard = span.enter();
+//!
+//!     // Captured as a "signed up" event for the person "12345", with a "plan" property.
+//!     tracing::info!(posthog = true, plan = "pro", "signed up");
+//!
+//!     Ok(())
+//! }
+//! ```
+
+use std::{collections::HashMap, fmt};
+
+use serde_json::Value;
+use tracing::{
+    field::{Field, Visit},
+    span, Event as TracingEvent, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use crate::{
+    client::PosthogClient,
+    data::{Event, Person},
+};
+
+/// Converts selected `tracing` events into Posthog events.
+///
+/// An event is forwarded when its level is enabled and it either has the marker field or its
+/// target starts with one of the configured targets. The message becomes the event name, the
+/// other fields become properties, and the distinct ID is taken from the closest span (or the
+/// event itself) with the distinct ID field. Events without a distinct ID are captured anonymously.
+pub struct PosthogLayer {
+    client: PosthogClient,
+    targets: Vec<String>,
+    level: Level,
+    marker_field: Option<String>,
+    distinct_id_field: String,
+}
+
+impl PosthogLayer {
+    pub fn builder(client: PosthogClient) -> PosthogLayerBuilder {
+        PosthogLayerBuilder::new(client)
+    }
+
+    fn is_selected(&self, event: &TracingEvent<'_>) -> bool {
+        let target = event.metadata().target();
+
+        // Forwarding the client's own diagnostics could enqueue events endlessly.
+        if *event.metadata().level() > self.level || target.starts_with(env!("CARGO_CRATE_NAME")) {
+            return false;
+        }
+
+        if self.targets.iter().any(|prefix| target.starts_with(prefix)) {
+            return true;
+        }
+
+        self.marker_field
+            .as_ref()
+            .is_some_and(|marker| event.metadata().fields().field(marker).is_some())
+    }
+}
+
+pub struct PosthogLayerBuilder {
+    client: PosthogClient,
+    targets: Vec<String>,
+    level: Level,
+    marker_field: Option<String>,
+    distinct_id_field: String,
+}
+
+impl PosthogLayerBuilder {
+    fn new(client: PosthogClient) -> Self {
+        Self {
+            client,
+            targets: vec![],
+            level: Level::TRACE,
+            marker_field: Some("posthog".to_string()),
+            distinct_id_field: "distinct_id".to_string(),
+        }
+    }
+
+    /// Forwards every event whose target starts with this prefix. Can be called multiple times.
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.targets.push(target.into());
+        self
+    }
+
+    /// Only forwards events at this level or more severe. Defaults to [`Level::TRACE`].
+    pub fn level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Forwards every event that has this field, regardless of its target. The field itself is
+    /// not sent as a property. Defaults to `posthog`.
+    pub fn marker_field(mut self, marker_field: impl Into<String>) -> Self {
+        self.marker_field = Some(marker_field.into());
+        self
+    }
+
+    /// Only selects events by target.
+    pub fn without_marker_field(mut self) -> Self {
+        self.marker_field = None;
+        self
+    }
+
+    /// Sets the span or event field that holds the distinct ID. Defaults to `distinct_id`.
+    pub fn distinct_id_field(mut self, distinct_id_field: impl Into<String>) -> Self {
+        self.distinct_id_field = distinct_id_field.into();
+        self
+    }
+
+    pub fn build(self) -> PosthogLayer {
+        PosthogLayer {
+            client: self.client,
+            targets: self.targets,
+            level: self.level,
+            marker_field: self.marker_field,
+            distinct_id_field: self.distinct_id_field,
+        }
+    }
+}
+
+/// The distinct ID recorded on a span, stored in its extensions.
+struct SpanDistinctId(String);
+
+impl<S> Layer<S> for PosthogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let mut visitor = DistinctIdVisitor::new(&self.distinct_id_field);
+        attrs.record(&mut visitor);
+
+        if let (Some(distinct_id), Some(span)) = (visitor.distinct_id, ctx.span(id)) {
+            span.extensions_mut().replace(SpanDistinctId(distinct_id));
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let mut visitor = DistinctIdVisitor::new(&self.distinct_id_field);
+        values.record(&mut visitor);
+
+        if let (Some(distinct_id), Some(span)) = (visitor.distinct_id, ctx.span(id)) {
+            span.extensions_mut().replace(SpanDistinctId(distinct_id));
+        }
+    }
+
+    fn on_event(&self, event: &TracingEvent<'_>, ctx: Context<'_, S>) {
+        if !self.is_selected(event) {
+            return;
+        }
+
+        let mut visitor = PropertyVisitor::default();
+        event.record(&mut visitor);
+
+        let mut properties = visitor.properties;
+        if let Some(marker) = &self.marker_field {
+            properties.remove(marker);
+        }
+
+        let name = match properties.remove("message") {
+            Some(Value::String(message)) => message,
+            Some(message) => message.to_string(),
+            None => event.metadata().name().to_string(),
+        };
+
+        let distinct_id = match properties.remove(&self.distinct_id_field) {
+            Some(Value::String(distinct_id)) => Some(distinct_id),
+            Some(distinct_id) => Some(distinct_id.to_string()),
+            None => ctx.event_scope(event).and_then(|scope| {
+                scope.from_root().fold(None, |distinct_id, span| {
+                    span.extensions()
+                        .get::<SpanDistinctId>()
+                        .map(|SpanDistinctId(id)| id.clone())
+                        .or(distinct_id)
+                })
+            }),
+        };
+
+        let Ok(event) = Event::builder().name(name).properties(properties).build() else {
+            return;
+        };
+
+        match distinct_id {
+            Some(distinct_id) => {
+                if let Ok(person) = Person::builder().distinct_id(distinct_id).build() {
+                    self.client.enqueue_event(&person, event).ok();
+                }
+            }
+            None => {
+                self.client.enqueue_anonymous_event(event).ok();
+            }
+        }
+    }
+}
+
+struct DistinctIdVisitor<'a> {
+    field: &'a str,
+    distinct_id: Option<String>,
+}
+
+impl<'a> DistinctIdVisitor<'a> {
+    fn new(field: &'a str) -> Self {
+        Self {
+            field,
+            distinct_id: None,
+        }
+    }
+}
+
+impl Visit for DistinctIdVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == self.field {
+            self.distinct_id = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == self.field {
+            self.distinct_id = Some(format!("{:?}", value));
+        }
+    }
+}
+
+#[derive(Default)]
+struct PropertyVisitor {
+    properties: HashMap<String, Value>,
+}
+
+impl Visit for PropertyVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.properties
+            .insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.properties
+            .insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.properties
+            .insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.properties
+            .insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.properties
+            .insert(field.name().to_string(), value.into());
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.properties
+            .insert(field.name().to_string(), value.to_string().into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.properties
+            .insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
@@ -55,10 +55,21 @@
 //!         println!("JSON feature flag: {:?}", json_flag);
 //!     }
 //!
+//!     // Send any queued events before exiting
+//!     client.shutdown(std::time::Duration::from_secs(5)).await?;
+//!
 //!     Ok(())
 //! }
 //! ```
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
 pub mod client;
 pub mod data;
 pub mod error;
+
+#[cfg(feature = "tracing-layer")]
+pub mod layer;
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
@@ -0,0 +1,292 @@
+//!
+//! Utilities for asserting on the requests your code sends to Posthog, without a Posthog instance.
+//!
+//! # Example
+//! ```no_run
+//! use hedgehog_rs::data::{Event, Person};
+//! use hedgehog_rs::testing::Recorder;
+//! use serde_json::json;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let recorder = Recorder::new();
+//!     recorder.set_feature_flags([("new-onboarding", json!(true))]);
+//!
+//!     let client = recorder.client().unwrap();
+//!     let mut person = Person::builder().distinct_id("user-1").build().unwrap();
+//!
+//!     let flags = client.feature_flags(&mut person).await.unwrap();
+//!     assert!(flags["new-onboarding"].variant_as_bool());
+//!
+//!     Event::builder()
+//!         .name("signed up")
+//!         .property("plan", "pro")
+//!         .build()
+//!         .unwrap()
+//!         .enqueue(&person, &client)
+//!         .unwrap();
+//!
+//!     let event = recorder.assert_captured("signed up");
+//!     assert_eq!(event.distinct_id(), "user-1");
+//!     assert_eq!(event.property("plan"), Some(&json!("pro")));
+//! }
+//! ```
+
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Map, Value};
+
+use crate::{
+    client::{PosthogClient, PosthogRequest, QueuedRequest},
+    error::PosthogError,
+};
+
+/// Records every request built by a [`PosthogClient`] instead of sending it, and answers
+/// feature flag and early access requests with scripted responses.
+///
+/// Clones share the same recording.
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    inner: Arc<Mutex<RecorderState>>,
+}
+
+#[derive(Debug, Default)]
+struct RecorderState {
+    requests: Vec<PosthogRequest>,
+    decide_response: Option<Value>,
+    early_access_response: Option<Value>,
+    flag_definitions_response: Option<Value>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a client that routes every request to this recorder.
+    ///
+    /// Like any client, it needs a Tokio runtime, so outside of one this returns
+    /// [`PosthogError::NoRuntime`].
+    pub fn client(&self) -> Result<PosthogClient, PosthogError> {
+        PosthogClient::builder()
+            .base_url("http://localhost")
+            .api_key("test-api-key")
+            .recorder(self.clone())
+            .build()
+    }
+
+    /// Every request recorded so far, in the order they were made.
+    pub fn requests(&self) -> Vec<PosthogRequest> {
+        self.state().requests.clone()
+    }
+
+    /// Every captured event, including those sent in batches.
+    pub fn events(&self) -> Vec<CapturedEvent> {
+        self.state()
+            .requests
+            .iter()
+            .flat_map(|request| match request {
+                PosthogRequest::CaptureEvent { body } => vec![body.clone()],
+                PosthogRequest::CaptureBatch { body } => {
+                    body["batch"].as_array().cloned().unwrap_or_default()
+                }
+                _ => vec![],
+            })
+            .map(CapturedEvent::from_json)
+            .collect()
+    }
+
+    pub fn event_names(&self) -> Vec<String> {
+        self.events().into_iter().map(|event| event.name).collect()
+    }
+
+    pub fn events_named(&self, name: &str) -> Vec<CapturedEvent> {
+        self.events()
+            .into_iter()
+            .filter(|event| event.name == name)
+            .collect()
+    }
+
+    pub fn events_for(&self, distinct_id: &str) -> Vec<CapturedEvent> {
+        self.events()
+            .into_iter()
+            .filter(|event| event.distinct_id == distinct_id)
+            .collect()
+    }
+
+    /// Panics unless an event with this name was captured, and returns the last one.
+    #[track_caller]
+    pub fn assert_captured(&self, name: &str) -> CapturedEvent {
+        match self.events_named(name).pop() {
+            Some(event) => event,
+            None => panic!(
+                "expected event {:?} to be captured, captured events: {:?}",
+                name,
+                self.event_names()
+            ),
+        }
+    }
+
+    /// Panics if an event with this name was captured.
+    #[track_caller]
+    pub fn assert_not_captured(&self, name: &str) {
+        let count = self.events_named(name).len();
+        assert!(
+            count == 0,
+            "expected event {:?} not to be captured, but it was captured {} time(s)",
+            name,
+            count
+        );
+    }
+
+    /// Panics unless exactly `expected` events with this name were captured.
+    #[track_caller]
+    pub fn assert_captured_times(&self, name: &str, expected: usize) {
+        let count = self.events_named(name).len();
+        assert!(
+            count == expected,
+            "expected event {:?} to be captured {} time(s), but it was captured {} time(s)",
+            name,
+            expected,
+            count
+        );
+    }
+
+    /// Forgets every recorded request. Scripted responses are kept.
+    pub fn clear(&self) {
+        self.state().requests.clear();
+    }
+
+    /// Sets the raw JSON returned for `/decide` requests.
+    pub fn set_decide_response(&self, response: Value) {
+        self.state().decide_response = Some(response);
+    }
+
+    /// Sets the feature flags returned for `/decide` requests.
+    pub fn set_feature_flags<K>(&self, flags: impl IntoIterator<Item = (K, Value)>)
+    where
+        K: Into<String>,
+    {
+        self.set_feature_flags_with_payloads(
+            flags.into_iter().map(|(key, variant)| (key, variant, None)),
+        );
+    }
+
+    /// Sets the feature flags and their payloads returned for `/decide` requests.
+    pub fn set_feature_flags_with_payloads<K>(
+        &self,
+        flags: impl IntoIterator<Item = (K, Value, Option<Value>)>,
+    ) where
+        K: Into<String>,
+    {
+        let mut feature_flags = Map::new();
+        let mut payloads = Map::new();
+
+        for (key, variant, payload) in flags {
+            let key = key.into();
+
+            // Posthog sends payloads as JSON encoded strings.
+            if let Some(payload) = payload {
+                payloads.insert(key.clone(), Value::String(payload.to_string()));
+            }
+
+            feature_flags.insert(key, variant);
+        }
+
+        self.set_decide_response(json!({
+            "featureFlags": feature_flags,
+            "featureFlagPayloads": payloads,
+        }));
+    }
+
+    /// Sets the features returned for early access feature requests.
+    ///
+    /// Each feature is an object with `id`, `name`, `description`, `stage` and `flagKey` fields.
+    pub fn set_early_access_features(&self, features: Vec<Value>) {
+        self.state().early_access_response = Some(json!({ "earlyAccessFeatures": features }));
+    }
+
+    /// Sets the raw JSON returned for local evaluation flag definition requests.
+    ///
+    /// Only used by clients built with a personal API key.
+    pub fn set_feature_flag_definitions(&self, response: Value) {
+        self.state().flag_definitions_response = Some(response);
+    }
+
+    pub(crate) fn record(&self, request: QueuedRequest) {
+        let response = {
+            let mut state = self.state();
+
+            let response = match &request.request {
+                PosthogRequest::EvaluateFeatureFlags { .. } => state
+                    .decide_response
+                    .clone()
+                    .unwrap_or_else(|| json!({ "featureFlags": {}, "featureFlagPayloads": {} })),
+
+                PosthogRequest::GetEarlyAccessFeatures { .. } => state
+                    .early_access_response
+                    .clone()
+                    .unwrap_or_else(|| json!({ "earlyAccessFeatures": [] })),
+
+                PosthogRequest::GetFeatureFlagDefinitions { .. } => state
+                    .flag_definitions_response
+                    .clone()
+                    .unwrap_or_else(|| json!({ "flags": [] })),
+
+                _ => json!({ "status": 1 }),
+            };
+
+            state.requests.push(request.request.clone());
+            response
+        };
+
+        if let Some(response_tx) = request.response_tx {
+            response_tx.send(Ok(response)).ok();
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, RecorderState> {
+        self.inner.lock().unwrap()
+    }
+}
+
+/// An event recorded by a [`Recorder`].
+#[derive(Debug, Clone)]
+pub struct CapturedEvent {
+    name: String,
+    distinct_id: String,
+    properties: Map<String, Value>,
+    body: Value,
+}
+
+impl CapturedEvent {
+    fn from_json(body: Value) -> Self {
+        Self {
+            name: body["event"].as_str().unwrap_or_default().to_string(),
+            distinct_id: body["distinct_id"].as_str().unwrap_or_default().to_string(),
+            properties: body["properties"].as_object().cloned().unwrap_or_default(),
+            body,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn distinct_id(&self) -> &str {
+        &self.distinct_id
+    }
+
+    pub fn properties(&self) -> &Map<String, Value> {
+        &self.properties
+    }
+
+    pub fn property(&self, key: &str) -> Option<&Value> {
+        self.properties.get(key)
+    }
+
+    /// The full JSON body of the event, as it would have been sent to Posthog.
+    pub fn json(&self) -> &Value {
+        &self.body
+    }
+}